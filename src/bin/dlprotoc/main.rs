@@ -0,0 +1,29 @@
+//! downloads protoc into a directory and prints the path to the protoc executable.
+//!
+//! The version is read from the protoc-version or .protoc-version file in the current directory or
+//! its parents, the same as build scripts that use dlprotoc.
+
+use std::path::Path;
+
+use dlprotoc::Config;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = std::env::args().collect::<Vec<String>>();
+    if args.len() != 2 {
+        eprintln!("Usage: dlprotoc (destination directory)");
+        std::process::exit(1);
+    }
+    let destination_dir = Path::new(&args[1]);
+
+    let installation = Config::new().download_to(destination_dir)?;
+    let platform = installation
+        .platform()
+        .map_or_else(String::new, |platform| format!(" for {platform}"));
+    eprintln!(
+        "dlprotoc: installed protoc {}{platform}",
+        installation.version()
+    );
+    println!("{}", installation.protoc_path().display());
+
+    Ok(())
+}
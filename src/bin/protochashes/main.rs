@@ -1,8 +1,9 @@
-//! downloads a version of protoc and prints hashes for all OSes and CPU architectures.
+//! downloads a version of protoc and prints hashes for all OSes and CPU architectures, and the
+//! release metadata with the publish date from GitHub.
 
 use std::fmt::Write;
 
-use dlprotoc::{CPUArch, OS, download_unverified, protoc_hash};
+use dlprotoc::{Platform, ProtocVersion, download_unverified, protoc_hash};
 
 fn hex_string(bytes: &[u8]) -> String {
     let mut s = String::new();
@@ -12,27 +13,84 @@ fn hex_string(bytes: &[u8]) -> String {
     s
 }
 
+/// Returns the publish date of the release from the GitHub API, as (year, month, day).
+fn published_date(version: ProtocVersion) -> Result<(u16, u8, u8), Box<dyn std::error::Error>> {
+    let url =
+        format!("https://api.github.com/repos/protocolbuffers/protobuf/releases/tags/v{version}");
+    let body = reqwest::blocking::Client::builder()
+        .user_agent("dlprotoc-protochashes")
+        .build()?
+        .get(url)
+        .send()?
+        .error_for_status()?
+        .text()?;
+
+    // avoids a JSON dependency: finds "published_at": "2024-05-23T..."
+    let published_at = body
+        .split_once("\"published_at\"")
+        .and_then(|(_, rest)| rest.split('"').nth(1))
+        .ok_or("missing published_at")?;
+    let mut parts = published_at
+        .get(..10)
+        .ok_or("invalid published_at")?
+        .split('-');
+    let mut next = || parts.next().ok_or("invalid published_at");
+    Ok((next()?.parse()?, next()?.parse()?, next()?.parse()?))
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = std::env::args().collect::<Vec<String>>();
-    if args.len() != 2 {
-        eprintln!("Usage: protochashes (protoc version e.g 27.0)");
+    if args.len() < 2 {
+        eprintln!(
+            "Usage: protochashes (protoc version e.g 27.0) [platforms e.g. linux-x86_64 aarch64-apple-darwin]"
+        );
         std::process::exit(1);
     }
-    let version = args[1].as_str();
-
-    for os in OS::all() {
-        for cpu in CPUArch::all() {
-            let bytes = download_unverified(*os, *cpu, version)?;
-            let hash = protoc_hash(&bytes);
-
-            println!("KnownVersion {{");
-            println!("    os: OS::{},", os.rust_identifier());
-            println!("    cpu: CPUArch::{},", cpu.code_label());
-            println!("    version: {version:#?},");
-            println!("    hash: hex!(\"{}\"),", hex_string(&hash));
-            println!("}},");
-        }
+    let version = args[1].parse::<ProtocVersion>()?;
+
+    // older releases do not include all platforms: only fail if the platform was requested
+    let explicit_platforms = args.len() > 2;
+    let platforms = if explicit_platforms {
+        args[2..]
+            .iter()
+            .map(|arg| arg.parse())
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        Platform::all()
+    };
+
+    for platform in platforms {
+        let bytes = match download_unverified(platform.os, platform.cpu, version) {
+            Ok(bytes) => bytes,
+            Err(e) if !explicit_platforms => {
+                eprintln!("protochashes: skipping {platform}: {e:#}");
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let hash = protoc_hash(&bytes);
+
+        println!("KnownVersion {{");
+        println!("    os: OS::{},", platform.os.rust_identifier());
+        println!("    cpu: CPUArch::{},", platform.cpu.code_label());
+        println!("    version: {},", version.rust_expression());
+        println!("    hash: hex!(\"{}\"),", hex_string(&hash));
+        println!("}},");
     }
 
+    let published = match published_date(version) {
+        Ok((year, month, day)) => format!("Some(ReleaseDate::new({year}, {month}, {day}))"),
+        Err(e) => {
+            eprintln!("protochashes: unknown publish date: {e}");
+            String::from("None")
+        }
+    };
+    println!();
+    println!("ReleaseInfo::new(");
+    println!("    {},", version.rust_expression());
+    println!("    {published},");
+    println!("    None,");
+    println!("),");
+
     Ok(())
 }
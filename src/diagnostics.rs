@@ -0,0 +1,275 @@
+//! Reports warnings and progress messages without mixing them into build script directives.
+//! With the `log` or `tracing` features, messages are also sent to the caller's logger, and each
+//! [`Stage`] is a span with its duration and byte count.
+
+use std::{
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::error::{Error, ErrorKind};
+
+/// How much is reported, from the `DLPROTOC_LOG` environment variable.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off,
+    #[default]
+    Warn,
+    Info,
+    Debug,
+}
+
+impl FromStr for Level {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "warn" => Ok(Self::Warn),
+            "info" => Ok(Self::Info),
+            "debug" => Ok(Self::Debug),
+            _ => Err(Error::new(
+                ErrorKind::Environment,
+                format!("invalid log level: {s:?}; expected off, warn, info or debug"),
+            )),
+        }
+    }
+}
+
+/// Where diagnostics are written.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Output {
+    /// A Cargo build script: warnings use `cargo::warning=` so Cargo shows them, and other
+    /// messages go to stderr, which Cargo saves in the build output.
+    BuildScript,
+    /// The `dlprotoc` command: everything goes to stderr, so stdout only has the result.
+    Command,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Diagnostics {
+    output: Output,
+    level: Level,
+}
+
+impl Diagnostics {
+    pub const fn new(output: Output, level: Level) -> Self {
+        Self { output, level }
+    }
+
+    /// A problem the user should fix or know about.
+    pub fn warning(self, message: impl Display) {
+        #[cfg(feature = "log")]
+        log::warn!(target: "dlprotoc", "{message}");
+        #[cfg(feature = "tracing")]
+        tracing::warn!(target: "dlprotoc", "{message}");
+        if let Some(line) = self.format(Level::Warn, message) {
+            match self.output {
+                Output::BuildScript => println!("{line}"),
+                Output::Command => eprintln!("{line}"),
+            }
+        }
+    }
+
+    /// What dlprotoc did, such as which release it selected.
+    pub fn info(self, message: impl Display) {
+        #[cfg(feature = "log")]
+        log::info!(target: "dlprotoc", "{message}");
+        #[cfg(feature = "tracing")]
+        tracing::info!(target: "dlprotoc", "{message}");
+        if let Some(line) = self.format(Level::Info, message) {
+            eprintln!("{line}");
+        }
+    }
+
+    /// Details for debugging, such as the URLs that are downloaded.
+    pub fn debug(self, message: impl Display) {
+        #[cfg(feature = "log")]
+        log::debug!(target: "dlprotoc", "{message}");
+        #[cfg(feature = "tracing")]
+        tracing::debug!(target: "dlprotoc", "{message}");
+        if let Some(line) = self.format(Level::Debug, message) {
+            eprintln!("{line}");
+        }
+    }
+
+    /// Starts timing `stage`. The duration is reported at the debug level when the returned
+    /// guard is dropped.
+    pub fn stage(self, stage: Stage) -> StageGuard {
+        StageGuard {
+            diagnostics: self,
+            stage,
+            start: Instant::now(),
+            bytes: None,
+            #[cfg(feature = "tracing")]
+            span: stage.span().entered(),
+        }
+    }
+
+    /// Returns the line to write for a message at `level`, or None if it is not enabled.
+    fn format(self, level: Level, message: impl Display) -> Option<String> {
+        if level > self.level {
+            return None;
+        }
+        let line = match (level, self.output) {
+            (Level::Warn, Output::BuildScript) => format!("cargo::warning=dlprotoc: {message}"),
+            (Level::Warn, Output::Command) => format!("dlprotoc: warning: {message}"),
+            (Level::Info, _) => format!("dlprotoc: {message}"),
+            _ => format!("dlprotoc: debug: {message}"),
+        };
+        // a directive ends at the end of the line
+        Some(line.replace('\n', " "))
+    }
+}
+
+/// A step that may be slow, which is timed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Selecting the release, including reading the project settings.
+    Resolve,
+    /// Downloading a release from one URL.
+    Download,
+    /// Hashing a release.
+    Verify,
+    /// Unzipping a release.
+    Extract,
+}
+
+impl Stage {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Resolve => "resolve",
+            Self::Download => "download",
+            Self::Verify => "verify",
+            Self::Extract => "extract",
+        }
+    }
+
+    /// Returns a new span for this stage. Span names must be literals.
+    #[cfg(feature = "tracing")]
+    fn span(self) -> tracing::Span {
+        use tracing::field::Empty;
+        match self {
+            Self::Resolve => {
+                tracing::info_span!(target: "dlprotoc", "resolve", elapsed_ms = Empty)
+            }
+            Self::Download => {
+                tracing::info_span!(target: "dlprotoc", "download", elapsed_ms = Empty, bytes = Empty)
+            }
+            Self::Verify => {
+                tracing::info_span!(target: "dlprotoc", "verify", elapsed_ms = Empty, bytes = Empty)
+            }
+            Self::Extract => {
+                tracing::info_span!(target: "dlprotoc", "extract", elapsed_ms = Empty, bytes = Empty)
+            }
+        }
+    }
+}
+
+/// Reports the duration of a [`Stage`] when it is dropped.
+#[derive(Debug)]
+pub struct StageGuard {
+    diagnostics: Diagnostics,
+    stage: Stage,
+    start: Instant,
+    bytes: Option<usize>,
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+}
+
+impl StageGuard {
+    /// Sets the number of bytes downloaded, hashed or unzipped.
+    pub const fn set_bytes(&mut self, bytes: usize) {
+        self.bytes = Some(bytes);
+    }
+
+    /// Returns the message reporting that the stage took `elapsed`.
+    fn message(&self, elapsed: Duration) -> String {
+        let name = self.stage.name();
+        match self.bytes {
+            Some(bytes) => format!("{name} took {elapsed:.2?} for {bytes} bytes"),
+            None => format!("{name} took {elapsed:.2?}"),
+        }
+    }
+}
+
+impl Drop for StageGuard {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        #[cfg(feature = "tracing")]
+        {
+            let elapsed_ms = u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX);
+            self.span.record("elapsed_ms", elapsed_ms);
+            if let Some(bytes) = self.bytes {
+                self.span.record("bytes", bytes);
+            }
+        }
+        self.diagnostics.debug(self.message(elapsed));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let build_script = Diagnostics::new(Output::BuildScript, Level::Info);
+        assert_eq!(
+            Some(String::from("cargo::warning=dlprotoc: a b")),
+            build_script.format(Level::Warn, "a\nb")
+        );
+        assert_eq!(
+            Some(String::from("dlprotoc: selected")),
+            build_script.format(Level::Info, "selected")
+        );
+        assert_eq!(None, build_script.format(Level::Debug, "url"));
+
+        let command = Diagnostics::new(Output::Command, Level::Debug);
+        assert_eq!(
+            Some(String::from("dlprotoc: warning: w")),
+            command.format(Level::Warn, "w")
+        );
+        assert_eq!(
+            Some(String::from("dlprotoc: debug: url")),
+            command.format(Level::Debug, "url")
+        );
+
+        let off = Diagnostics::new(Output::BuildScript, Level::Off);
+        assert_eq!(None, off.format(Level::Warn, "w"));
+    }
+
+    #[test]
+    fn test_stage() {
+        let diagnostics = Diagnostics::new(Output::Command, Level::Debug);
+        let mut stage = diagnostics.stage(Stage::Download);
+        let elapsed = Duration::from_millis(1500);
+        assert_eq!(
+            Some(String::from("dlprotoc: debug: download took 1.50s")),
+            diagnostics.format(Level::Debug, stage.message(elapsed))
+        );
+        stage.set_bytes(42);
+        assert_eq!(
+            Some(String::from(
+                "dlprotoc: debug: download took 1.50s for 42 bytes"
+            )),
+            diagnostics.format(Level::Debug, stage.message(elapsed))
+        );
+
+        // the timing line is only written at the debug level
+        let warn = Diagnostics::new(Output::BuildScript, Level::Warn);
+        assert_eq!(None, warn.format(Level::Debug, stage.message(elapsed)));
+    }
+
+    #[test]
+    fn test_parse_level() {
+        assert_eq!(Level::Debug, "DEBUG\n".parse().unwrap());
+        assert_eq!(Level::Off, "off".parse().unwrap());
+        let err = "verbose".parse::<Level>().unwrap_err();
+        assert_eq!(
+            "invalid log level: \"verbose\"; expected off, warn, info or debug",
+            err.to_string()
+        );
+    }
+}
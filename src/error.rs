@@ -4,52 +4,189 @@ use zip::result::ZipError;
 
 /// The Error type returned by the dlprotoc crate.
 ///
-/// This type only contains a message, so it is not expected to be handled.
+/// The message describes the problem for people. Use [`Error::kind`] to handle some errors
+/// differently, for example to fall back to another protoc if downloading fails, while still
+/// failing the build if a download does not match its expected hash.
+///
+/// Errors caused by another error, such as an I/O error, return it from
+/// [`std::error::Error::source`]. Display only writes this error's message; the alternate form
+/// (`{:#}`) also writes the messages of its sources, separated by `: `.
 #[derive(Debug)]
 pub struct Error {
+    kind: ErrorKind,
     message: String,
+    source: Option<Box<dyn std::error::Error + Send + Sync>>,
+}
+
+/// The category of an [`Error`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Downloading a release failed, e.g. the source could not be reached or returned an error.
+    Network,
+    /// A downloaded release did not match its expected hash, or a different hash is already
+    /// recorded for it. This may mean the release was tampered with.
+    HashMismatch,
+    /// No known or trusted hash exists for the requested version, or no known version matches
+    /// the requirement.
+    UnknownVersion,
+    /// The release is known bad, and using known bad releases is not allowed.
+    KnownBad,
+    /// protoc has no release for the operating system or CPU architecture.
+    UnsupportedPlatform,
+    /// Reading or writing a file failed.
+    Io,
+    /// A release could not be unzipped.
+    Archive,
+    /// The environment is missing something or is invalid, e.g. `OUT_DIR` is not set because this
+    /// is not running in a build script, or an installed protoc does not run or its version does
+    /// not match.
+    Environment,
+    /// A release needs to be downloaded, but downloads are disabled because no download sources
+    /// are configured (e.g. `sources = []` for builds without network access).
+    Offline,
+    /// Any other error, such as invalid settings.
+    Other,
 }
 
 impl Error {
+    #[must_use]
+    pub const fn new(kind: ErrorKind, message: String) -> Self {
+        Self {
+            kind,
+            message,
+            source: None,
+        }
+    }
+
+    /// Returns an error with [`ErrorKind::Other`].
     #[must_use]
     pub const fn from_string(message: String) -> Self {
-        Self { message }
+        Self::new(ErrorKind::Other, message)
     }
 
+    /// Returns an error with [`ErrorKind::Other`].
     pub fn with_prefix(prefix: impl Borrow<str>, e: impl Display) -> Self {
+        Self::from_string(format!("{}: {e}", prefix.borrow()))
+    }
+
+    #[must_use]
+    pub const fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Returns an error caused by `source`.
+    pub(crate) fn with_source(
+        kind: ErrorKind,
+        message: String,
+        source: impl std::error::Error + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            kind,
+            message,
+            source: Some(Box::new(source)),
+        }
+    }
+
+    /// Returns an [`ErrorKind::Io`] error caused by `e`, with `context` such as the file name.
+    pub(crate) fn io(context: impl Display, e: std::io::Error) -> Self {
+        Self::with_source(ErrorKind::Io, context.to_string(), e)
+    }
+
+    /// Returns an error caused by `e`, with `context` such as what was being unzipped.
+    pub(crate) fn zip(context: impl Display, e: ZipError) -> Self {
+        let kind = match e {
+            ZipError::Io(_) => ErrorKind::Io,
+            _ => ErrorKind::Archive,
+        };
+        Self::with_source(kind, context.to_string(), e)
+    }
+
+    /// Returns this error with `prefix` added to the message, keeping the kind and source.
+    #[must_use]
+    pub(crate) fn prefixed(self, prefix: impl Borrow<str>) -> Self {
         Self {
-            message: format!("{}: {e}", prefix.borrow()),
+            message: format!("{}: {}", prefix.borrow(), self.message),
+            ..self
         }
     }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.message)
+        write!(f, "{}", self.message)?;
+        if f.alternate() {
+            let mut source = std::error::Error::source(self);
+            while let Some(e) = source {
+                write!(f, ": {e}")?;
+                source = e.source();
+            }
+        }
+        Ok(())
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source
+            .as_deref()
+            .map(|source| source as &(dyn std::error::Error + 'static))
+    }
+}
 
 impl From<ZipError> for Error {
     fn from(e: zip::result::ZipError) -> Self {
-        Self::with_prefix("zip error", e)
+        Self::zip("zip error", e)
     }
 }
 
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
-        Self::with_prefix("io error", e)
+        Self::io("io error", e)
     }
 }
 
 impl From<reqwest::Error> for Error {
     fn from(e: reqwest::Error) -> Self {
         let message = if let Some(url) = e.url() {
-            format!("failed downloading protoc from url: {url}: {e}")
+            format!("failed downloading protoc from url: {url}")
         } else {
-            format!("failed downloading protoc: {e}")
+            String::from("failed downloading protoc")
         };
-        Self::from_string(message)
+        Self::with_source(ErrorKind::Network, message, e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kind() {
+        assert_eq!(
+            ErrorKind::Other,
+            Error::from_string(String::from("x")).kind()
+        );
+        let err = Error::new(ErrorKind::HashMismatch, String::from("hash mismatch")).prefixed("a");
+        assert_eq!(ErrorKind::HashMismatch, err.kind());
+        assert_eq!("a: hash mismatch", err.to_string());
+
+        let err = Error::from(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert_eq!(ErrorKind::Io, err.kind());
+    }
+
+    #[test]
+    fn test_source() {
+        let io_error = std::io::Error::new(std::io::ErrorKind::NotFound, "not found");
+        let err = Error::io("reading a.zip", io_error).prefixed("cache");
+        assert_eq!("cache: reading a.zip", err.to_string());
+        assert_eq!("cache: reading a.zip: not found", format!("{err:#}"));
+        let source = std::error::Error::source(&err).unwrap();
+        assert!(source.is::<std::io::Error>());
+
+        let err = Error::from(ZipError::InvalidArchive("bad".into()));
+        assert_eq!(ErrorKind::Archive, err.kind());
+        assert!(std::error::Error::source(&err).unwrap().is::<ZipError>());
+        assert!(std::error::Error::source(&Error::from_string(String::new())).is_none());
     }
 }
@@ -0,0 +1,159 @@
+//! Finds protoc executables that are already installed, and checks their versions.
+
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    error::{Error, ErrorKind},
+    protoc_version::ProtocVersion,
+};
+
+/// Controls whether a build script uses a protoc installed on the system, such as from apt or
+/// Homebrew, or downloads protoc.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Strategy {
+    /// Use `protoc` from `PATH`. It is an error if it is missing or its version does not match.
+    System,
+    /// Always download protoc.
+    #[default]
+    Download,
+    /// Use `protoc` from `PATH` if its version matches, otherwise download protoc.
+    SystemThenDownload,
+}
+
+/// Parses the lower-case variant name, with words separated by `-` (e.g.
+/// `system-then-download`).
+impl FromStr for Strategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "system" => Ok(Self::System),
+            "download" => Ok(Self::Download),
+            "system-then-download" => Ok(Self::SystemThenDownload),
+            _ => Err(Error::from_string(format!(
+                "invalid strategy: {s:?}: must be system, download or system-then-download"
+            ))),
+        }
+    }
+}
+
+/// Returns the first `protoc` file in the directories of `path_var`, which uses the format of the
+/// `PATH` environment variable.
+pub fn find_in_path(path_var: &str) -> Option<PathBuf> {
+    std::env::split_paths(path_var)
+        .map(|dir| dir.join("protoc"))
+        .find(|path| path.is_file())
+}
+
+/// Runs `protoc --version` and returns the version it reports.
+pub fn query_version(protoc: &Path) -> Result<ProtocVersion, Error> {
+    let output = std::process::Command::new(protoc)
+        .arg("--version")
+        .output()
+        .map_err(|e| Error::io(format_args!("running {}", protoc.display()), e))?;
+    if !output.status.success() {
+        return Err(Error::new(
+            ErrorKind::Environment,
+            format!("running {} --version: {}", protoc.display(), output.status),
+        ));
+    }
+    parse_version_output(&String::from_utf8_lossy(&output.stdout))
+        .map_err(|e| e.prefixed(protoc.display().to_string()))
+}
+
+/// Parses the output of `protoc --version`, such as `libprotoc 33.5`.
+fn parse_version_output(output: &str) -> Result<ProtocVersion, Error> {
+    output
+        .trim()
+        .strip_prefix("libprotoc ")
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::Environment,
+                format!(
+                    "unexpected --version output: {:?}; expected libprotoc and a version",
+                    output.trim()
+                ),
+            )
+        })?
+        .parse()
+}
+
+/// Writes an executable shell script named `protoc` in `dir` that runs `command`, such as
+/// `echo libprotoc 33.5`, and returns its path.
+#[cfg(test)]
+pub fn write_fake_protoc(dir: &Path, command: &str) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let protoc_path = dir.join("protoc");
+    std::fs::write(&protoc_path, format!("#!/bin/sh\n{command}\n")).unwrap();
+    std::fs::set_permissions(&protoc_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    protoc_path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version_output() {
+        assert_eq!(
+            ProtocVersion::new(33, 5),
+            parse_version_output("libprotoc 33.5\n").unwrap()
+        );
+        assert_eq!(
+            ProtocVersion::new(21, 12),
+            parse_version_output("libprotoc 3.21.12\n").unwrap()
+        );
+        assert_eq!(
+            "34.0-rc1".parse::<ProtocVersion>().unwrap(),
+            parse_version_output("libprotoc 34.0-rc1\n").unwrap()
+        );
+        let err = parse_version_output("protoc fake version\n").unwrap_err();
+        assert_eq!(
+            "unexpected --version output: \"protoc fake version\"; expected libprotoc and a version",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse_strategy() {
+        assert_eq!(Strategy::System, "system".parse().unwrap());
+        assert_eq!(
+            Strategy::SystemThenDownload,
+            "system-then-download".parse().unwrap()
+        );
+        assert!("System".parse::<Strategy>().is_err());
+    }
+
+    #[test]
+    fn test_find_in_path() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let empty_dir = tempdir.path().join("empty");
+        let bin_dir = tempdir.path().join("bin");
+        std::fs::create_dir_all(&empty_dir).unwrap();
+        std::fs::create_dir_all(&bin_dir).unwrap();
+        let path_var = std::env::join_paths([&empty_dir, &bin_dir]).unwrap();
+        let path_var = path_var.to_str().unwrap();
+        assert_eq!(None, find_in_path(path_var));
+
+        std::fs::write(bin_dir.join("protoc"), "").unwrap();
+        assert_eq!(Some(bin_dir.join("protoc")), find_in_path(path_var));
+    }
+
+    #[test]
+    fn test_query_version() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let err = query_version(&tempdir.path().join("missing")).unwrap_err();
+        assert_eq!(ErrorKind::Io, err.kind());
+
+        let protoc = write_fake_protoc(tempdir.path(), "echo libprotoc 33.5");
+        assert_eq!(ProtocVersion::new(33, 5), query_version(&protoc).unwrap());
+
+        let protoc = write_fake_protoc(tempdir.path(), "exit 1");
+        let err = query_version(&protoc).unwrap_err();
+        assert_eq!(ErrorKind::Environment, err.kind());
+    }
+}
@@ -19,38 +19,97 @@ In `build.rs`, call [`download_protoc`] before calling `compile_protos`:
 ```no_run
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     dlprotoc::download_protoc()?;
+    println!("cargo::rerun-if-changed=src/example.proto");
     prost_build::compile_protos(&["src/example.proto"], &["src/"])?;
     Ok(())
 }
 ```
+
+If settings are read from `Cargo.toml`, a protoc version file, or `DLPROTOC_*` environment
+variables, [`download_protoc`] prints `cargo::rerun-if-changed` directives for them. Cargo then
+only reruns the build script when a listed file changes, so print a directive for each `.proto`
+file as well.
+
+# Features
+
+* `log`: sends warnings and progress messages to the [`log`](https://docs.rs/log) crate.
+* `tracing`: sends them to [`tracing`](https://docs.rs/tracing), with spans that time resolving,
+  downloading, verifying and extracting protoc.
 */
 
-use std::{io::Cursor, path::Path};
+use std::{
+    borrow::Cow,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
 
+use diagnostics::{Diagnostics, Stage};
 use sha2::{Digest, Sha256};
 
+mod diagnostics;
 mod error;
+mod installed;
+mod lockfile;
+mod manifest;
+mod protoc_version;
+mod version_file;
 mod versions;
 
-pub use error::Error;
+pub use error::{Error, ErrorKind};
 
 pub type CPUArch = versions::CPUArch;
 pub type OS = versions::OS;
-use versions::known_hash;
+pub type Platform = versions::Platform;
+pub type ProtocVersion = protoc_version::ProtocVersion;
+pub type VersionReq = protoc_version::VersionReq;
+pub type UniversalBinary = versions::UniversalBinary;
+pub type Strategy = installed::Strategy;
+pub type KnownVersion = versions::KnownVersion;
+pub type ReleaseInfo = versions::ReleaseInfo;
+pub type ReleaseDate = versions::ReleaseDate;
+pub type Advisory = versions::Advisory;
+pub type Severity = versions::Severity;
+pub use versions::{LATEST_VERSION, known_releases, latest_for, release_info, versions_for};
 
 // Cargo's build output environment variable. See:
 // https://doc.rust-lang.org/cargo/reference/environment-variables.html#environment-variables-cargo-sets-for-build-scripts
 const CARGO_BUILD_OUT_ENV_VAR: &str = "OUT_DIR";
 
+// Cargo's package directory, used to read settings from Cargo.toml.
+const CARGO_MANIFEST_DIR_ENV_VAR: &str = "CARGO_MANIFEST_DIR";
+
+// Cargo's host target triple for build scripts. Build scripts run on the host, so this is the
+// platform that needs to run protoc.
+const CARGO_HOST_ENV_VAR: &str = "HOST";
+
+// Overrides the platform of the downloaded protoc. Either os-cpu (e.g. linux-x86_64) or a Rust
+// target triple.
+const PLATFORM_ENV_VAR: &str = "DLPROTOC_PLATFORM";
+
+// Overrides the protoc version, either an exact version or a requirement. Must match a known
+// version.
+const VERSION_ENV_VAR: &str = "DLPROTOC_VERSION";
+
+// Sets which diagnostics are printed: off, warn (the default), info or debug. Warnings use
+// cargo::warning= in build scripts; other messages go to stderr.
+const LOG_ENV_VAR: &str = "DLPROTOC_LOG";
+
+// The directories searched for a system protoc, with Strategy::System or
+// Strategy::SystemThenDownload.
+const PATH_ENV_VAR: &str = "PATH";
+
 // Prost uses the PROTOC env var to find the protoc executable. See:
 // https://docs.rs/prost-build/latest/prost_build/#sourcing-protoc
 const PROST_PROTOC_ENV_VAR: &str = "PROTOC";
 
-/// Returns the URL to download the protoc release. The version is the format major.minor, such as "27.0".
-fn make_url(os: OS, cpu: CPUArch, version: &str) -> String {
-    format!(
-        "https://github.com/protocolbuffers/protobuf/releases/download/v{version}/protoc-{version}-{os}-{cpu}.zip"
-    )
+/// The default source of protoc releases. Other sources must use the same layout.
+const GITHUB_RELEASES_URL: &str = "https://github.com/protocolbuffers/protobuf/releases/download";
+
+/// Returns the URL to download the protoc release from `source`.
+fn make_url(source: &str, os: OS, cpu: CPUArch, version: ProtocVersion) -> String {
+    let source = source.trim_end_matches('/');
+    let file_version = version.file_version();
+    format!("{source}/v{version}/protoc-{file_version}-{os}-{cpu}.zip")
 }
 
 /// Downloads protoc without verifying the hash. This should only be used by the dlprotoc
@@ -59,8 +118,20 @@ fn make_url(os: OS, cpu: CPUArch, version: &str) -> String {
 /// # Errors
 ///
 /// Returns an error if it fails to fetch protoc over the Internet.
-pub fn download_unverified(os: OS, cpu: CPUArch, version: &str) -> Result<Vec<u8>, Error> {
-    let url = make_url(os, cpu, version);
+pub fn download_unverified(os: OS, cpu: CPUArch, version: ProtocVersion) -> Result<Vec<u8>, Error> {
+    download_url(&make_url(GITHUB_RELEASES_URL, os, cpu, version))
+}
+
+/// Downloads `url`, reporting the time and size.
+fn download_timed(diagnostics: Diagnostics, url: &str) -> Result<Vec<u8>, Error> {
+    diagnostics.debug(format_args!("downloading {url}"));
+    let mut stage = diagnostics.stage(Stage::Download);
+    let data = download_url(url).inspect_err(|e| diagnostics.debug(format_args!("{e:#}")))?;
+    stage.set_bytes(data.len());
+    Ok(data)
+}
+
+fn download_url(url: &str) -> Result<Vec<u8>, Error> {
     let response = reqwest::blocking::get(url)?.error_for_status()?;
     let bytes = response.bytes()?;
 
@@ -68,22 +139,327 @@ pub fn download_unverified(os: OS, cpu: CPUArch, version: &str) -> Result<Vec<u8
     Ok(bytes.as_ref().to_vec())
 }
 
-fn fetch_current() -> Result<Vec<u8>, Error> {
-    let os = OS::current();
-    let cpu = CPUArch::current();
-    let version = versions::LATEST_VERSION;
+/// Returns the platform that runs protoc, using the environment variables from the build script.
+fn host_platform(inputs: &mut BuildInputs) -> Result<Platform, Error> {
+    let platform_override = inputs.env_var(PLATFORM_ENV_VAR)?;
+    let host = optional_env_var(CARGO_HOST_ENV_VAR)?;
+    select_host_platform(platform_override.as_deref(), host.as_deref())
+}
+
+/// Files and environment variables that were read, so build scripts can rerun when they change.
+/// Variables that Cargo sets for build scripts, such as `OUT_DIR`, are not included.
+#[derive(Debug, Default)]
+struct BuildInputs {
+    paths: Vec<PathBuf>,
+    env_vars: Vec<&'static str>,
+    /// True if an input changed the default settings, e.g. an environment variable was set or a
+    /// settings file was found.
+    used: bool,
+}
+
+impl BuildInputs {
+    /// Returns the value of an environment variable, or None if it is not set.
+    fn env_var(&mut self, name: &'static str) -> Result<Option<String>, Error> {
+        if !self.env_vars.contains(&name) {
+            self.env_vars.push(name);
+        }
+        let value = optional_env_var(name)?;
+        self.used |= value.is_some();
+        Ok(value)
+    }
+
+    /// Adds a file that changed the default settings.
+    fn add_path(&mut self, path: PathBuf) {
+        self.paths.push(path);
+        self.used = true;
+    }
+
+    /// Returns the diagnostics for `output`, using the level from `DLPROTOC_LOG`.
+    fn diagnostics(&mut self, output: diagnostics::Output) -> Result<Diagnostics, Error> {
+        let level = match self.env_var(LOG_ENV_VAR)? {
+            Some(level) => level
+                .parse()
+                .map_err(|e: Error| e.prefixed(format!("env var {LOG_ENV_VAR}")))?,
+            None => diagnostics::Level::default(),
+        };
+        Ok(Diagnostics::new(output, level))
+    }
+
+    /// Prints the Cargo build script directives to rerun when the inputs change. Prints nothing if
+    /// only the defaults were used: after any rerun directive, Cargo no longer reruns the build
+    /// script when other files in the package change, such as `.proto` files.
+    fn print_rerun_directives(&self) {
+        if !self.used {
+            return;
+        }
+        for path in &self.paths {
+            println!("cargo::rerun-if-changed={}", path.display());
+        }
+        for name in &self.env_vars {
+            println!("cargo::rerun-if-env-changed={name}");
+        }
+    }
+}
+
+/// Returns the value of an environment variable, or None if it is not set.
+fn optional_env_var(name: &str) -> Result<Option<String>, Error> {
+    match std::env::var(name) {
+        Ok(value) => Ok(Some(value)),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(Error::with_source(
+            ErrorKind::Environment,
+            format!("env var {name}"),
+            e,
+        )),
+    }
+}
+
+/// Parses the `DLPROTOC_VERSION` override, which must match a version in `known_versions` unless
+/// `allow_unknown` is true. Errors suggest versions with a release for `platform`.
+fn parse_version_override(
+    value: &str,
+    known_versions: &[KnownVersion],
+    platform: Platform,
+    universal_binary: UniversalBinary,
+    allow_unknown: bool,
+) -> Result<VersionReq, Error> {
+    let requirement = value.trim().parse::<VersionReq>()?;
+    if allow_unknown
+        || known_versions
+            .iter()
+            .any(|known| requirement.matches(known.version()))
+    {
+        return Ok(requirement);
+    }
+    let hint = versions::unknown_requirement_hint(
+        known_versions,
+        platform,
+        universal_binary,
+        &requirement,
+    );
+    Err(Error::new(
+        ErrorKind::UnknownVersion,
+        format!("no known protoc version matches {requirement}: {hint}"),
+    ))
+}
+
+fn select_host_platform(
+    platform_override: Option<&str>,
+    host: Option<&str>,
+) -> Result<Platform, Error> {
+    if let Some(platform) = platform_override {
+        return platform
+            .parse()
+            .map_err(|e: Error| e.prefixed(format!("env var {PLATFORM_ENV_VAR}")));
+    }
+    if let Some(host) = host {
+        return Platform::from_target_triple(host)
+            .map_err(|e| e.prefixed(format!("env var {CARGO_HOST_ENV_VAR}")));
+    }
+    Platform::detect()
+}
+
+/// Returns a warning if the release has an advisory. Returns an error if it is known bad, unless
+/// `allow_known_bad` is true.
+fn check_advisory(
+    info: Option<&ReleaseInfo>,
+    allow_known_bad: bool,
+) -> Result<Option<String>, Error> {
+    let Some(info) = info else {
+        return Ok(None);
+    };
+    let Some(advisory) = info.advisory() else {
+        return Ok(None);
+    };
+    let version = info.version();
+    match advisory.severity {
+        Severity::KnownBad if !allow_known_bad => Err(Error::new(
+            ErrorKind::KnownBad,
+            format!(
+                "protoc {version} is known bad: {}; set allow-known-bad to use it anyway",
+                advisory.reason
+            ),
+        )),
+        Severity::KnownBad => Ok(Some(format!(
+            "using known bad protoc {version}: {}",
+            advisory.reason
+        ))),
+        Severity::Warning => Ok(Some(format!("protoc {version}: {}", advisory.reason))),
+    }
+}
+
+/// Returns a warning if `version` is at least `max_behind` minor releases older than
+/// [`LATEST_VERSION`].
+fn check_outdated(version: ProtocVersion, max_behind: u32) -> Option<String> {
+    let behind = versions::minor_releases_behind(
+        version,
+        versions::known_releases().map(KnownVersion::version),
+    );
+    if behind == 0 || behind < max_behind as usize {
+        return None;
+    }
+    Some(format!(
+        "protoc {version} is {behind} minor releases behind the latest known version {LATEST_VERSION}"
+    ))
+}
 
-    let expected_hash = known_hash(os, cpu, version)?;
-    let data = download_unverified(OS::current(), CPUArch::current(), version)?;
-    let actual_hash = protoc_hash(&data);
-    if expected_hash != actual_hash {
-        return Err(Error::from_string(format!(
-            "hash mismatch for {os} {cpu} {version}",
-        )));
+/// Returns the verified Zip data for the release. Uses `cache_dir` if it contains the release,
+/// otherwise downloads it from the first source that has it, and stores it in `cache_dir`. Data
+/// with the wrong hash is saved in `quarantine_dir`, if it is set.
+fn fetch_verified(
+    diagnostics: Diagnostics,
+    release: &versions::Release,
+    sources: &[String],
+    cache_dir: Option<&Path>,
+    quarantine_dir: Option<&Path>,
+) -> Result<Vec<u8>, Error> {
+    let cache_path = cache_dir.map(|cache_dir| cache_path(cache_dir, release));
+    if let Some(cache_path) = &cache_path
+        && let Some(data) = read_cached(diagnostics, cache_path, release, quarantine_dir)?
+    {
+        diagnostics.debug(format_args!("using cached {}", cache_path.display()));
+        return Ok(data);
+    }
+
+    let data = download_release(diagnostics, release, sources, quarantine_dir)?;
+    if let Some(cache_path) = &cache_path {
+        write_file(cache_path, &data)?;
     }
     Ok(data)
 }
 
+fn cache_path(cache_dir: &Path, release: &versions::Release) -> PathBuf {
+    cache_dir.join(format!(
+        "protoc-{}-{}.zip",
+        release.version, release.platform
+    ))
+}
+
+/// Downloads the release from the first source that has it and verifies its hash.
+fn download_release(
+    diagnostics: Diagnostics,
+    release: &versions::Release,
+    sources: &[String],
+    quarantine_dir: Option<&Path>,
+) -> Result<Vec<u8>, Error> {
+    let Platform { os, cpu } = release.platform;
+    let mut errors = Vec::new();
+    for source in sources {
+        let url = make_url(source, os, cpu, release.version);
+        match download_timed(diagnostics, &url) {
+            Ok(data) => {
+                verify_hash(diagnostics, release, &data, &url, quarantine_dir)?;
+                return Ok(data);
+            }
+            Err(e) => errors.push((url, e)),
+        }
+    }
+    Err(download_failed(errors))
+}
+
+/// Returns the error for failing to download from every source. `errors` has the URL and error for
+/// each attempt. A single error is returned unchanged. Otherwise the message lists the URLs that
+/// failed first, and the last error is the source.
+fn download_failed(mut errors: Vec<(String, Error)>) -> Error {
+    let Some((_, last)) = errors.pop() else {
+        return Error::new(
+            ErrorKind::Offline,
+            String::from("no protoc download sources configured"),
+        );
+    };
+    if errors.is_empty() {
+        return last;
+    }
+    let urls = errors
+        .iter()
+        .map(|(url, _)| url.as_str())
+        .collect::<Vec<_>>();
+    Error::with_source(
+        last.kind(),
+        format!("failed downloading protoc from {}", urls.join(", ")),
+        last,
+    )
+}
+
+/// Returns an error with both hashes if `data` does not match the release. `source` is the URL
+/// or file `data` was read from. If `quarantine_dir` is set, the data is saved there, named with
+/// its actual hash, so it can be inspected.
+fn verify_hash(
+    diagnostics: Diagnostics,
+    release: &versions::Release,
+    data: &[u8],
+    source: &str,
+    quarantine_dir: Option<&Path>,
+) -> Result<(), Error> {
+    let actual_hash = hash_timed(diagnostics, data);
+    if release.hash == actual_hash {
+        return Ok(());
+    }
+    let actual_hash = versions::format_hash(&actual_hash);
+    let mut message = format!(
+        "hash mismatch for protoc {} {} from {source}: expected sha256 {}, got {actual_hash} ({} bytes)",
+        release.version,
+        release.platform,
+        versions::format_hash(&release.hash),
+        data.len()
+    );
+    if let Some(quarantine_dir) = quarantine_dir {
+        let path = quarantine_dir.join(format!(
+            "protoc-{}-{}-{actual_hash}.zip",
+            release.version, release.platform
+        ));
+        match write_file(&path, data) {
+            Ok(()) => message = format!("{message}; saved to {}", path.display()),
+            Err(e) => diagnostics.warning(format_args!("failed to quarantine protoc: {e:#}")),
+        }
+    }
+    Err(Error::new(ErrorKind::HashMismatch, message))
+}
+
+/// Returns the cached release, or None if it is not cached or the cached file has the wrong hash.
+fn read_cached(
+    diagnostics: Diagnostics,
+    path: &Path,
+    release: &versions::Release,
+    quarantine_dir: Option<&Path>,
+) -> Result<Option<Vec<u8>>, Error> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Error::io(format_args!("reading {}", path.display()), e)),
+    };
+    let source = path.display().to_string();
+    match verify_hash(diagnostics, release, &data, &source, quarantine_dir) {
+        Ok(()) => Ok(Some(data)),
+        Err(e) => {
+            diagnostics.warning(format_args!("ignoring cached protoc: {e}"));
+            Ok(None)
+        }
+    }
+}
+
+/// Writes a file, creating its directory if needed.
+pub(crate) fn write_file(path: &Path, data: &[u8]) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| Error::io(format_args!("creating {}", dir.display()), e))?;
+    }
+    // write a temporary file then rename it, so concurrent builds never read a partial file
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+    std::fs::write(&temp_path, data)
+        .and_then(|()| std::fs::rename(&temp_path, path))
+        .map_err(|e| Error::io(format_args!("writing {}", path.display()), e))
+}
+
+/// Hashes a release, reporting the time and size.
+fn hash_timed(diagnostics: Diagnostics, data: &[u8]) -> [u8; 32] {
+    let mut stage = diagnostics.stage(Stage::Verify);
+    stage.set_bytes(data.len());
+    protoc_hash(data)
+}
+
 /// Hashes data using the algorithm used to verify protoc binaries (currently SHA-256). This should
 /// only be used by the `protochashes` tool.
 #[must_use]
@@ -97,50 +473,762 @@ pub fn protoc_hash(data: &[u8]) -> [u8; 32] {
     result
 }
 
-fn write_protoc(destination_dir: &Path) -> Result<(), Error> {
-    // downloads protoc for the current platform, checking the hashes
-    let protoc_zip_bytes = fetch_current()?;
+fn write_protoc(
+    diagnostics: Diagnostics,
+    config: &Config,
+    resolved: &Resolved,
+    destination_dir: &Path,
+) -> Result<(), Error> {
+    let sources = config.download_sources();
+    let protoc_zip_bytes = match &resolved.data {
+        Some(data) => data,
+        // downloads protoc for the selected platform, checking the hashes
+        None => &fetch_verified(
+            diagnostics,
+            &resolved.release,
+            &sources,
+            config.cache_dir.as_deref(),
+            config.quarantine_dir.as_deref(),
+        )
+        .map_err(|e| match &resolved.lockfile_path {
+            Some(path) => e.prefixed(format!("verifying with {}", path.display())),
+            None => e,
+        })?,
+    };
 
-    write_protoc_zip_data(destination_dir, &protoc_zip_bytes)
+    let mut stage = diagnostics.stage(Stage::Extract);
+    stage.set_bytes(protoc_zip_bytes.len());
+    write_protoc_zip_data(destination_dir, protoc_zip_bytes)
+}
+
+/// Downloads a release that is not known to this crate from the first source that has it, and
+/// records its hash in the lockfile. Returns the release and its Zip data.
+fn record_release(
+    diagnostics: Diagnostics,
+    lockfile: &mut lockfile::Lockfile,
+    candidates: &[Platform],
+    version: ProtocVersion,
+    sources: &[String],
+    cache_dir: Option<&Path>,
+) -> Result<(versions::Release, Vec<u8>), Error> {
+    let mut errors = Vec::new();
+    for &platform in candidates {
+        for source in sources {
+            let url = make_url(source, platform.os, platform.cpu, version);
+            let data = match download_timed(diagnostics, &url) {
+                Ok(data) => data,
+                Err(e) => {
+                    errors.push((url, e));
+                    continue;
+                }
+            };
+            let release = versions::Release {
+                version,
+                platform,
+                hash: hash_timed(diagnostics, &data),
+            };
+            lockfile.record(lockfile::LockedRelease {
+                version,
+                platform,
+                hash: release.hash,
+            })?;
+            diagnostics.warning(format_args!(
+                "recorded the hash of unknown protoc {version} for {platform} in {}",
+                lockfile.path().display()
+            ));
+            if let Some(cache_dir) = cache_dir {
+                write_file(&cache_path(cache_dir, &release), &data)?;
+            }
+            return Ok((release, data));
+        }
+    }
+    Err(download_failed(errors))
 }
 
 /// Downloads protoc to the `OUT_DIR` environment variable and sets the `PROTOC` environment
-/// variable so prost-build or tonic-build can find it.
+/// variable so prost-build or tonic-build can find it. Uses the default [`Config`].
+///
+/// Intended to be called from a Cargo build script (`build.rs`). The protoc platform is the
+/// build script's `HOST` target triple, or the platform running this function if `HOST` is not
+/// set. The `DLPROTOC_PLATFORM` environment variable overrides it, either as os-cpu (e.g.
+/// `linux-x86_64`) or a target triple.
+///
+/// Warnings are shown by Cargo with `cargo::warning=`. Set `DLPROTOC_LOG` to `info` or `debug`
+/// to write more details to the build script's stderr, or `off` to hide warnings.
 ///
-/// Intended to be called from a Cargo build script (`build.rs`).
+/// If any settings were read from Cargo.toml, a protoc version file, or a `DLPROTOC_*`
+/// environment variable, this prints `cargo::rerun-if-changed` and `cargo::rerun-if-env-changed`
+/// directives for all of them, and Cargo then only reruns the build script when one of them or
+/// another listed input changes. Otherwise it prints nothing, so Cargo keeps rerunning the build
+/// script when any package file changes, but does not rerun it when `DLPROTOC_VERSION` is first
+/// set.
 ///
 /// # Errors
 ///
-/// Returns an [`Error`] if it fails to fetch protoc over the Internet, fails to verify it, or
-/// fails to unzip it.
+/// Returns an [`Error`] if it is run on an unsupported platform, fails to fetch protoc over the
+/// Internet, fails to verify it, or fails to unzip it.
 pub fn download_protoc() -> Result<(), Error> {
-    let out_dir = std::env::var(CARGO_BUILD_OUT_ENV_VAR)
-        .map_err(|e| Error::with_prefix(format!("env var {CARGO_BUILD_OUT_ENV_VAR}"), e))?;
-    let protoc_distribution_path = Path::new(&out_dir).join("protoc_zip");
-    if protoc_distribution_path.exists() {
-        // TODO: version the download so updates are applied: Does Cargo solve this for us?
-        println!(
-            "dlprotoc: warning: not downloading; protoc already exists at {}",
-            protoc_distribution_path.display()
-        );
-    } else {
-        write_protoc(&protoc_distribution_path)?;
+    Config::new().download_protoc().map(|_| ())
+}
+
+/// Options that control which protoc release is downloaded. [`download_protoc`] uses the
+/// defaults.
+///
+/// The `DLPROTOC_VERSION` environment variable overrides the version, so a different protoc can
+/// be tried without changing code. It must match a known version, unless
+/// [`Config::trust_on_first_use`] is enabled. Cargo reruns the build script when it changes only
+/// if the previous run used some setting; see [`download_protoc`].
+///
+/// Options that are not set are read from the `[package.metadata.dlprotoc]` table in the
+/// package's Cargo.toml, then from the `[workspace.metadata.dlprotoc]` table in the workspace's
+/// Cargo.toml. The keys are `version`, `sources`, `cache-dir`, `quarantine-dir`,
+/// `universal-binary`, `prereleases`, `trust-on-first-use`, `trusted-hashes`, `allow-known-bad`,
+/// `warn-outdated`, `use-existing-protoc` and `strategy`, matching the methods on this type. If the
+/// version is still not set, it is read from a `protoc-version` or `.protoc-version` file in the
+/// package directory or the closest parent directory. This file contains a single version or
+/// requirement, such as `33.5` or `>=31`.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    version: Option<VersionReq>,
+    sources: Option<Vec<String>>,
+    cache_dir: Option<PathBuf>,
+    quarantine_dir: Option<PathBuf>,
+    universal_binary: Option<UniversalBinary>,
+    prereleases: Option<bool>,
+    trust_on_first_use: Option<bool>,
+    trusted_hashes: Vec<KnownVersion>,
+    allow_known_bad: Option<bool>,
+    warn_outdated: Option<u32>,
+    use_existing_protoc: Option<bool>,
+    strategy: Option<Strategy>,
+    /// The directory containing the package's Cargo.toml, or the current directory. Set by
+    /// [`Config::with_project_settings`].
+    project_dir: Option<PathBuf>,
+}
+
+impl Config {
+    /// Returns the default options.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    let protoc_path = protoc_distribution_path.join("bin").join("protoc");
-    unsafe {
-        std::env::set_var(PROST_PROTOC_ENV_VAR, protoc_path);
+    /// Sets the protoc version to download, either an exact [`ProtocVersion`] or a [`VersionReq`].
+    /// A requirement selects the newest known version that matches and has a release for the
+    /// platform. The default is the latest known version.
+    #[must_use]
+    pub fn version(mut self, version: impl Into<VersionReq>) -> Self {
+        self.version = Some(version.into());
+        self
     }
 
-    Ok(())
+    /// Sets the base URLs to download protoc releases from, which are tried in order. Each source
+    /// must use the same layout as the GitHub releases: `{source}/v{version}/{file name}`. The
+    /// default is `https://github.com/protocolbuffers/protobuf/releases/download`. Releases are
+    /// always verified with the known hashes. With no sources, only releases in the cache
+    /// directory can be used, and other releases fail with [`ErrorKind::Offline`].
+    #[must_use]
+    pub fn sources(mut self, sources: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.sources = Some(sources.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets a directory to store downloaded protoc releases, so they are only downloaded once
+    /// across builds. The default is to not cache releases.
+    #[must_use]
+    pub fn cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    /// Sets a directory to save downloads that do not match the expected hash, so they can be
+    /// inspected. Each file is named with the release and its actual SHA-256 hash. The default is
+    /// to not save them. The build fails either way.
+    #[must_use]
+    pub fn quarantine_dir(mut self, quarantine_dir: impl Into<PathBuf>) -> Self {
+        self.quarantine_dir = Some(quarantine_dir.into());
+        self
+    }
+
+    /// Sets when the Mac OS X universal binary is used. The default is
+    /// `UniversalBinary::Fallback`. This has no effect on other operating systems.
+    #[must_use]
+    pub const fn universal_binary(mut self, universal_binary: UniversalBinary) -> Self {
+        self.universal_binary = Some(universal_binary);
+        self
+    }
+
+    /// Sets whether prereleases (release candidates) may be selected. If true, version
+    /// requirements match prereleases in their range, and the default version is the newest known
+    /// version, including prereleases. The default is false: prereleases are only used if a
+    /// requirement names one, such as `34.0-rc1` or `>=34.0-rc1`.
+    #[must_use]
+    pub const fn prereleases(mut self, prereleases: bool) -> Self {
+        self.prereleases = Some(prereleases);
+        self
+    }
+
+    /// Sets whether versions without a known hash may be downloaded. If true and the version is an
+    /// exact version this crate does not know, the first download records its SHA-256 hash in
+    /// `dlprotoc.lock` next to Cargo.toml, and later downloads are verified with the recorded
+    /// hash. Commit the lockfile so every build uses the same protoc. The default is false.
+    #[must_use]
+    pub const fn trust_on_first_use(mut self, trust_on_first_use: bool) -> Self {
+        self.trust_on_first_use = Some(trust_on_first_use);
+        self
+    }
+
+    /// Adds the expected SHA-256 hash of the release for `version` and `platform`, which may be a
+    /// version this crate does not know. This overrides the hash built into this crate for the
+    /// same release. Builds that use a trusted hash log a warning.
+    #[must_use]
+    pub fn trusted_hash(
+        mut self,
+        version: ProtocVersion,
+        platform: Platform,
+        hash: [u8; 32],
+    ) -> Self {
+        self.trusted_hashes
+            .push(KnownVersion::new(version, platform, hash));
+        self
+    }
+
+    /// Sets whether releases with a `Severity::KnownBad` advisory may be used. If false, version
+    /// requirements skip known bad releases, and an exact known bad version is an error. If true,
+    /// using one prints a warning. The default is false.
+    #[must_use]
+    pub const fn allow_known_bad(mut self, allow_known_bad: bool) -> Self {
+        self.allow_known_bad = Some(allow_known_bad);
+        self
+    }
+
+    /// Warns if the selected protoc is `max_behind` or more minor releases older than
+    /// [`LATEST_VERSION`], e.g. 33.5 is 2 minor releases behind 34.1. Build scripts emit the
+    /// warning with `cargo::warning=`, so Cargo shows it. The default is to not check.
+    #[must_use]
+    pub const fn warn_outdated(mut self, max_behind: u32) -> Self {
+        self.warn_outdated = Some(max_behind);
+        self
+    }
+
+    /// Sets whether [`Config::download_protoc`] uses the protoc set by the `PROTOC` environment
+    /// variable, such as one provided by a Nix shell, instead of downloading protoc. It is used if
+    /// it runs and reports a version that matches [`Config::version`]; any version matches if the
+    /// version is not set. Otherwise, protoc is downloaded with a warning. The default is false.
+    #[must_use]
+    pub const fn use_existing_protoc(mut self, use_existing_protoc: bool) -> Self {
+        self.use_existing_protoc = Some(use_existing_protoc);
+        self
+    }
+
+    /// Sets whether [`Config::download_protoc`] uses `protoc` from `PATH`, such as one installed
+    /// with apt or Homebrew, or downloads protoc. The system protoc is used if it reports a
+    /// version that matches [`Config::version`]; any version matches if the version is not set.
+    /// The default is `Strategy::Download`. [`Config::download_to`] always downloads.
+    #[must_use]
+    pub const fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = Some(strategy);
+        self
+    }
+
+    /// Returns these options, with the version from `DLPROTOC_VERSION` if it is set, and options
+    /// that are not set read from the Cargo.toml manifests and the protoc version file. The files
+    /// are found from `CARGO_MANIFEST_DIR`, or the current directory if it is not set.
+    fn with_project_settings(&self, inputs: &mut BuildInputs) -> Result<Self, Error> {
+        let mut config = self.clone();
+        let version_override = inputs.env_var(VERSION_ENV_VAR)?;
+        let manifest_dir = optional_env_var(CARGO_MANIFEST_DIR_ENV_VAR)?;
+
+        if let Some(manifest_dir) = &manifest_dir {
+            let loaded = manifest::load(Path::new(manifest_dir))?;
+            let settings = loaded.settings;
+            config.version = config.version.or(settings.version);
+            config.sources = config.sources.or(settings.sources);
+            config.cache_dir = config.cache_dir.or(settings.cache_dir);
+            config.quarantine_dir = config.quarantine_dir.or(settings.quarantine_dir);
+            config.universal_binary = config.universal_binary.or(settings.universal_binary);
+            config.prereleases = config.prereleases.or(settings.prereleases);
+            config.trust_on_first_use = config.trust_on_first_use.or(settings.trust_on_first_use);
+            config.allow_known_bad = config.allow_known_bad.or(settings.allow_known_bad);
+            config.warn_outdated = config.warn_outdated.or(settings.warn_outdated);
+            config.use_existing_protoc =
+                config.use_existing_protoc.or(settings.use_existing_protoc);
+            config.strategy = config.strategy.or(settings.strategy);
+            // trusted hashes set with methods take precedence
+            config
+                .trusted_hashes
+                .extend(settings.trusted_hashes.unwrap_or_default());
+            inputs.paths.extend(loaded.paths);
+            inputs.used |= loaded.found;
+        }
+
+        let trust_on_first_use = config.trust_on_first_use.unwrap_or(false);
+        if let Some(version) = version_override {
+            let version = parse_version_override(
+                &version,
+                &versions::with_trusted_hashes(&config.trusted_hashes),
+                host_platform(inputs)?,
+                config.universal_binary.unwrap_or_default(),
+                trust_on_first_use,
+            )
+            .map_err(|e| e.prefixed(format!("env var {VERSION_ENV_VAR}")))?;
+            config.version = Some(version);
+        }
+
+        let project_dir = match manifest_dir {
+            Some(manifest_dir) => PathBuf::from(manifest_dir),
+            None => std::env::current_dir()
+                .map_err(|e| Error::io("reading the current directory", e))?,
+        };
+        if config.version.is_none()
+            && let Some(version_file) = version_file::find(&project_dir)?
+        {
+            config.version = Some(version_file.version);
+            inputs.add_path(version_file.path);
+        }
+        config.project_dir = Some(project_dir);
+        Ok(config)
+    }
+
+    /// Downloads protoc using these options. See [`download_protoc`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`Error`] if it is run on an unsupported platform, fails to read the project
+    /// settings, fails to fetch protoc over the Internet, fails to verify it, or fails to unzip it.
+    pub fn download_protoc(&self) -> Result<Installation, Error> {
+        let mut inputs = BuildInputs::default();
+        let out_dir = std::env::var(CARGO_BUILD_OUT_ENV_VAR).map_err(|e| {
+            Error::with_source(
+                ErrorKind::Environment,
+                format!("env var {CARGO_BUILD_OUT_ENV_VAR}"),
+                e,
+            )
+        })?;
+
+        let diagnostics = inputs.diagnostics(diagnostics::Output::BuildScript)?;
+
+        let stage = diagnostics.stage(Stage::Resolve);
+        let config = self.with_project_settings(&mut inputs)?;
+        if let Some(installation) = config.installed_protoc(diagnostics, &mut inputs)? {
+            drop(stage);
+            inputs.print_rerun_directives();
+            unsafe {
+                std::env::set_var(PROST_PROTOC_ENV_VAR, &installation.protoc_path);
+            }
+            return Ok(installation);
+        }
+        let resolved = config.resolve_host_release(diagnostics, &mut inputs)?;
+        drop(stage);
+        inputs.print_rerun_directives();
+        let release = resolved.release;
+
+        // include the version and platform so changing them downloads again
+        let protoc_distribution_path =
+            Path::new(&out_dir).join(format!("protoc-{}-{}", release.version, release.platform));
+        if protoc_distribution_path.exists() {
+            diagnostics.info(format_args!(
+                "not downloading; protoc already exists at {}",
+                protoc_distribution_path.display()
+            ));
+        } else {
+            write_protoc(diagnostics, &config, &resolved, &protoc_distribution_path)?;
+        }
+
+        let protoc_path = protoc_distribution_path.join("bin").join("protoc");
+        unsafe {
+            std::env::set_var(PROST_PROTOC_ENV_VAR, &protoc_path);
+        }
+
+        Ok(Installation {
+            version: release.version,
+            platform: Some(release.platform),
+            protoc_path,
+        })
+    }
+
+    /// Downloads protoc using these options and extracts it into `destination_dir`. Unlike
+    /// [`Config::download_protoc`], this does not need to run in a build script, and does not set
+    /// the `PROTOC` environment variable. This is used by the `dlprotoc` command.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`Error`] if it is run on an unsupported platform, fails to read the project
+    /// settings, fails to fetch protoc over the Internet, fails to verify it, or fails to unzip it.
+    pub fn download_to(&self, destination_dir: &Path) -> Result<Installation, Error> {
+        let mut inputs = BuildInputs::default();
+        let diagnostics = inputs.diagnostics(diagnostics::Output::Command)?;
+        let (config, resolved) = self.resolve(diagnostics, &mut inputs)?;
+        write_protoc(diagnostics, &config, &resolved, destination_dir)?;
+        let release = resolved.release;
+
+        Ok(Installation {
+            version: release.version,
+            platform: Some(release.platform),
+            protoc_path: destination_dir.join("bin").join("protoc"),
+        })
+    }
+
+    /// Returns these options with the project settings, and the release to download for the
+    /// platform that runs protoc.
+    fn resolve(
+        &self,
+        diagnostics: Diagnostics,
+        inputs: &mut BuildInputs,
+    ) -> Result<(Self, Resolved), Error> {
+        let _stage = diagnostics.stage(Stage::Resolve);
+        let config = self.with_project_settings(inputs)?;
+        let resolved = config.resolve_host_release(diagnostics, inputs)?;
+        Ok((config, resolved))
+    }
+
+    /// Returns the release to download for the platform that runs protoc.
+    fn resolve_host_release(
+        &self,
+        diagnostics: Diagnostics,
+        inputs: &mut BuildInputs,
+    ) -> Result<Resolved, Error> {
+        let platform = host_platform(inputs)?;
+        let resolved = match self.resolve_release(platform) {
+            Ok(release) => Resolved {
+                release,
+                data: None,
+                lockfile_path: None,
+            },
+            Err(e) => match self.unknown_version() {
+                Some(version) => {
+                    let resolved = self.resolve_locked_release(diagnostics, platform, version)?;
+                    // a lockfile written by this run would make Cargo rerun the next build
+                    if resolved.data.is_none()
+                        && let Some(lockfile_path) = &resolved.lockfile_path
+                    {
+                        inputs.add_path(lockfile_path.clone());
+                    }
+                    resolved
+                }
+                None => return Err(e),
+            },
+        };
+        let release = resolved.release;
+        self.check_version(diagnostics, release.version)?;
+        let trusted_hash = self.trusted_hashes.iter().find(|trusted| {
+            trusted.version() == release.version && trusted.platform() == release.platform
+        });
+        if trusted_hash.is_some() {
+            diagnostics.warning(format_args!(
+                "using externally supplied hash for protoc {} {}",
+                release.version, release.platform
+            ));
+        }
+        if release.platform != platform {
+            diagnostics.info(format_args!(
+                "using compatible protoc release {} for platform {platform}",
+                release.platform
+            ));
+        }
+        diagnostics.info(format_args!(
+            "selected protoc {} for {}",
+            release.version, release.platform
+        ));
+        Ok(resolved)
+    }
+
+    /// Returns the installed protoc to use instead of downloading: the protoc set by `PROTOC` if
+    /// [`Config::use_existing_protoc`] is enabled, then the protoc on `PATH` if
+    /// [`Config::strategy`] allows it. Returns None if protoc should be downloaded.
+    fn installed_protoc(
+        &self,
+        diagnostics: Diagnostics,
+        inputs: &mut BuildInputs,
+    ) -> Result<Option<Installation>, Error> {
+        let installation = match self.existing_protoc(diagnostics, inputs)? {
+            Some(installation) => Some(installation),
+            None => self.system_protoc(diagnostics, inputs)?,
+        };
+        if let Some(installation) = &installation {
+            self.check_version(diagnostics, installation.version)?;
+        }
+        Ok(installation)
+    }
+
+    /// Warns if `version` has an advisory or is outdated. Returns an error if it is known bad and
+    /// [`Config::allow_known_bad`] is not enabled.
+    fn check_version(&self, diagnostics: Diagnostics, version: ProtocVersion) -> Result<(), Error> {
+        if let Some(warning) = check_advisory(
+            versions::release_info(version),
+            self.allow_known_bad.unwrap_or(false),
+        )? {
+            diagnostics.warning(warning);
+        }
+        if let Some(warning) = self
+            .warn_outdated
+            .and_then(|max_behind| check_outdated(version, max_behind))
+        {
+            diagnostics.warning(warning);
+        }
+        Ok(())
+    }
+
+    /// Returns the protoc on `PATH` if the strategy uses it and its version matches. Returns an
+    /// error if the strategy is `Strategy::System` and there is no matching protoc.
+    fn system_protoc(
+        &self,
+        diagnostics: Diagnostics,
+        inputs: &mut BuildInputs,
+    ) -> Result<Option<Installation>, Error> {
+        let strategy = self.strategy.unwrap_or_default();
+        if strategy == Strategy::Download {
+            return Ok(None);
+        }
+        let path_var = inputs.env_var(PATH_ENV_VAR)?.unwrap_or_default();
+        let protoc_path = installed::find_in_path(&path_var);
+        if let Some(protoc_path) = &protoc_path {
+            // rerun when the system protoc is upgraded
+            inputs.add_path(protoc_path.clone());
+        }
+        match (self.check_system_protoc(protoc_path), strategy) {
+            (Ok(installation), _) => {
+                diagnostics.info(format_args!(
+                    "using system protoc {} at {}",
+                    installation.version,
+                    installation.protoc_path.display()
+                ));
+                Ok(Some(installation))
+            }
+            (Err(e), Strategy::SystemThenDownload) => {
+                diagnostics.info(format_args!("not using system protoc: {e:#}; downloading"));
+                Ok(None)
+            }
+            (Err(e), _) => Err(e.prefixed("strategy is system")),
+        }
+    }
+
+    /// Returns the system protoc found on `PATH`, or an `ErrorKind::Environment` error if it was
+    /// not found or does not match.
+    fn check_system_protoc(&self, protoc_path: Option<PathBuf>) -> Result<Installation, Error> {
+        let Some(protoc_path) = protoc_path else {
+            return Err(Error::new(
+                ErrorKind::Environment,
+                format!("protoc not found in {PATH_ENV_VAR}"),
+            ));
+        };
+        let version = self.check_installed(&protoc_path).map_err(|e| {
+            Error::with_source(
+                ErrorKind::Environment,
+                format!("system protoc {}", protoc_path.display()),
+                e,
+            )
+        })?;
+        Ok(Installation {
+            version,
+            platform: None,
+            protoc_path,
+        })
+    }
+
+    /// Returns the protoc set by the `PROTOC` environment variable, if
+    /// [`Config::use_existing_protoc`] is enabled and it reports a version that matches. Returns
+    /// None if protoc should be downloaded.
+    fn existing_protoc(
+        &self,
+        diagnostics: Diagnostics,
+        inputs: &mut BuildInputs,
+    ) -> Result<Option<Installation>, Error> {
+        if !self.use_existing_protoc.unwrap_or(false) {
+            return Ok(None);
+        }
+        let Some(protoc_path) = inputs.env_var(PROST_PROTOC_ENV_VAR)? else {
+            return Ok(None);
+        };
+        let protoc_path = PathBuf::from(protoc_path);
+        match self.check_installed(&protoc_path) {
+            Ok(version) => {
+                diagnostics.info(format_args!(
+                    "using protoc {version} from {PROST_PROTOC_ENV_VAR}={}",
+                    protoc_path.display()
+                ));
+                Ok(Some(Installation {
+                    version,
+                    platform: None,
+                    protoc_path,
+                }))
+            }
+            Err(e) => {
+                diagnostics.warning(format_args!(
+                    "ignoring {PROST_PROTOC_ENV_VAR}={}: {e:#}; downloading protoc",
+                    protoc_path.display()
+                ));
+                Ok(None)
+            }
+        }
+    }
+
+    /// Returns the version of an installed protoc, or an error if it does not run or its version
+    /// does not match [`Config::version`]. Any version matches if the version is not set.
+    fn check_installed(&self, protoc_path: &Path) -> Result<ProtocVersion, Error> {
+        let version = installed::query_version(protoc_path)?;
+        if let Some(requirement) = &self.version {
+            let matches = if self.prereleases.unwrap_or(false) {
+                requirement.matches_prerelease(version)
+            } else {
+                requirement.matches(version)
+            };
+            if !matches {
+                return Err(Error::new(
+                    ErrorKind::Environment,
+                    format!("protoc {version} does not match {requirement}"),
+                ));
+            }
+        }
+        Ok(version)
+    }
+
+    /// Returns the base URLs to download releases from: [`Config::sources`], or GitHub if they
+    /// are not set.
+    fn download_sources(&self) -> Cow<'_, [String]> {
+        self.sources.as_deref().map_or_else(
+            || Cow::Owned(vec![String::from(GITHUB_RELEASES_URL)]),
+            Cow::Borrowed,
+        )
+    }
+
+    /// Returns the exact version to trust on first use, or None if it is not enabled.
+    fn unknown_version(&self) -> Option<ProtocVersion> {
+        if !self.trust_on_first_use.unwrap_or(false) {
+            return None;
+        }
+        self.version.as_ref().and_then(VersionReq::exact_version)
+    }
+
+    /// Returns the release recorded in the lockfile, or downloads the release and records it.
+    fn resolve_locked_release(
+        &self,
+        diagnostics: Diagnostics,
+        platform: Platform,
+        version: ProtocVersion,
+    ) -> Result<Resolved, Error> {
+        let project_dir = self
+            .project_dir
+            .as_deref()
+            .unwrap_or_else(|| Path::new("."));
+        let mut lockfile = lockfile::Lockfile::read(&project_dir.join(lockfile::LOCKFILE_NAME))?;
+        let candidates =
+            versions::candidate_releases(platform, self.universal_binary.unwrap_or_default());
+        for &candidate in &candidates {
+            if let Some(hash) = lockfile.find(version, candidate) {
+                return Ok(Resolved {
+                    release: versions::Release {
+                        version,
+                        platform: candidate,
+                        hash,
+                    },
+                    data: None,
+                    lockfile_path: Some(lockfile.path().to_path_buf()),
+                });
+            }
+        }
+
+        let (release, data) = record_release(
+            diagnostics,
+            &mut lockfile,
+            &candidates,
+            version,
+            &self.download_sources(),
+            self.cache_dir.as_deref(),
+        )?;
+        Ok(Resolved {
+            release,
+            data: Some(data),
+            lockfile_path: Some(lockfile.path().to_path_buf()),
+        })
+    }
+
+    fn resolve_release(&self, platform: Platform) -> Result<versions::Release, Error> {
+        let known_versions = versions::with_trusted_hashes(&self.trusted_hashes);
+        let universal_binary = self.universal_binary.unwrap_or_default();
+        let prereleases = self.prereleases.unwrap_or(false);
+        let excluded = if self.allow_known_bad.unwrap_or(false) {
+            Vec::new()
+        } else {
+            versions::known_bad_versions()
+        };
+        match &self.version {
+            Some(requirement) => versions::resolve_release(
+                &known_versions,
+                platform,
+                requirement,
+                universal_binary,
+                prereleases,
+                &excluded,
+            ),
+            None if prereleases => versions::resolve_release(
+                &known_versions,
+                platform,
+                &VersionReq::any(),
+                universal_binary,
+                true,
+                &excluded,
+            ),
+            None => versions::select_release(
+                &known_versions,
+                platform,
+                versions::LATEST_VERSION,
+                universal_binary,
+            ),
+        }
+    }
+}
+
+/// The release selected by [`Config::resolve_host_release`].
+struct Resolved {
+    release: versions::Release,
+    /// The Zip data, if it was downloaded to record its hash in the lockfile.
+    data: Option<Vec<u8>>,
+    /// The lockfile, if the hash was recorded in it.
+    lockfile_path: Option<PathBuf>,
+}
+
+/// The protoc installed by [`Config::download_protoc`] or [`Config::download_to`], or the existing
+/// protoc that was used instead.
+#[derive(Clone, Debug)]
+pub struct Installation {
+    version: ProtocVersion,
+    platform: Option<Platform>,
+    protoc_path: PathBuf,
+}
+
+impl Installation {
+    /// Returns the installed protoc version.
+    #[must_use]
+    pub const fn version(&self) -> ProtocVersion {
+        self.version
+    }
+
+    /// Returns the platform of the downloaded protoc release. This may be a compatible platform if
+    /// there is no release for the build platform. Returns None if an existing protoc was used.
+    #[must_use]
+    pub const fn platform(&self) -> Option<Platform> {
+        self.platform
+    }
+
+    /// Returns the path to the protoc executable.
+    #[must_use]
+    pub fn protoc_path(&self) -> &Path {
+        &self.protoc_path
+    }
 }
 
 /// Extracts files from the protoc distribution Zip data into `destination_dir`. This makes it
 /// easier to test the code without downloading anything.
 fn write_protoc_zip_data(destination_dir: &Path, protoc_zip_bytes: &[u8]) -> Result<(), Error> {
-    let mut zip = zip::ZipArchive::new(Cursor::new(&protoc_zip_bytes))?;
-    zip.extract(destination_dir)?;
-    Ok(())
+    let mut zip = zip::ZipArchive::new(Cursor::new(&protoc_zip_bytes))
+        .map_err(|e| Error::zip("reading protoc zip", e))?;
+    zip.extract(destination_dir).map_err(|e| {
+        Error::zip(
+            format_args!("extracting protoc to {}", destination_dir.display()),
+            e,
+        )
+    })
 }
 
 #[cfg(test)]
@@ -149,21 +1237,90 @@ mod tests {
     use zip::{ZipWriter, write::SimpleFileOptions};
 
     use super::*;
-    use versions::LATEST_VERSION;
+
+    const TEST_DIAGNOSTICS: Diagnostics =
+        Diagnostics::new(diagnostics::Output::Command, diagnostics::Level::Off);
 
     #[test]
     fn test_make_url() {
-        let url = make_url(OS::Linux, CPUArch::X86_64, "27.0");
+        let url = make_url(
+            GITHUB_RELEASES_URL,
+            OS::Linux,
+            CPUArch::X86_64,
+            ProtocVersion::new(27, 0),
+        );
         assert_eq!(
             url,
             "https://github.com/protocolbuffers/protobuf/releases/download/v27.0/protoc-27.0-linux-x86_64.zip"
         );
 
-        let url = make_url(OS::OSX, CPUArch::AArch64, "26.1");
+        let url = make_url(
+            GITHUB_RELEASES_URL,
+            OS::OSX,
+            CPUArch::AArch64,
+            ProtocVersion::new(26, 1),
+        );
         assert_eq!(
             url,
             "https://github.com/protocolbuffers/protobuf/releases/download/v26.1/protoc-26.1-osx-aarch_64.zip"
         );
+
+        let url = make_url(
+            GITHUB_RELEASES_URL,
+            OS::OSX,
+            CPUArch::UniversalBinary,
+            ProtocVersion::new(34, 1),
+        );
+        assert_eq!(
+            url,
+            "https://github.com/protocolbuffers/protobuf/releases/download/v34.1/protoc-34.1-osx-universal_binary.zip"
+        );
+
+        // 3.x releases use the full version; 3.21.x was released as 21.x
+        let url = make_url(
+            GITHUB_RELEASES_URL,
+            OS::Linux,
+            CPUArch::X86_64,
+            "3.20.3".parse().unwrap(),
+        );
+        assert_eq!(
+            url,
+            "https://github.com/protocolbuffers/protobuf/releases/download/v3.20.3/protoc-3.20.3-linux-x86_64.zip"
+        );
+        let url = make_url(
+            GITHUB_RELEASES_URL,
+            OS::Linux,
+            CPUArch::X86_32,
+            "3.21.12".parse().unwrap(),
+        );
+        assert_eq!(
+            url,
+            "https://github.com/protocolbuffers/protobuf/releases/download/v21.12/protoc-21.12-linux-x86_32.zip"
+        );
+
+        // prerelease files use a different version format than the tag
+        let url = make_url(
+            GITHUB_RELEASES_URL,
+            OS::Linux,
+            CPUArch::X86_64,
+            "34.0-rc1".parse().unwrap(),
+        );
+        assert_eq!(
+            url,
+            "https://github.com/protocolbuffers/protobuf/releases/download/v34.0-rc1/protoc-34.0-rc-1-linux-x86_64.zip"
+        );
+
+        // sources may have a trailing slash
+        let url = make_url(
+            "https://mirror.example.com/protobuf/",
+            OS::Linux,
+            CPUArch::AArch64,
+            ProtocVersion::new(34, 1),
+        );
+        assert_eq!(
+            url,
+            "https://mirror.example.com/protobuf/v34.1/protoc-34.1-linux-aarch_64.zip"
+        );
     }
 
     struct SetEnvForTest<'a> {
@@ -250,6 +1407,7 @@ message M {
             err.to_string().contains("env var OUT_DIR"),
             "download_protoc unexpected error message: {err}"
         );
+        assert_eq!(ErrorKind::Environment, err.kind());
     }
 
     /// Tests most of the code without downloading anything.
@@ -295,6 +1453,415 @@ message M {
         );
     }
 
+    #[test]
+    fn test_fetch_verified_cache() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache_dir = tempdir.path().join("cache");
+        let data = b"fake protoc zip";
+        let release = versions::Release {
+            version: ProtocVersion::new(34, 1),
+            platform: Platform::new(OS::Linux, CPUArch::X86_64),
+            hash: protoc_hash(data),
+        };
+
+        // not cached: fails without any sources
+        let err =
+            fetch_verified(TEST_DIAGNOSTICS, &release, &[], Some(&cache_dir), None).unwrap_err();
+        assert_eq!("no protoc download sources configured", err.to_string());
+        assert_eq!(ErrorKind::Offline, err.kind());
+
+        write_file(&cache_dir.join("protoc-34.1-linux-x86_64.zip"), data).unwrap();
+        let cached =
+            fetch_verified(TEST_DIAGNOSTICS, &release, &[], Some(&cache_dir), None).unwrap();
+        assert_eq!(data.as_slice(), cached);
+
+        // a cached file with the wrong hash is ignored, and saved in the quarantine directory
+        let wrong_release = versions::Release {
+            hash: [0; 32],
+            ..release
+        };
+        let quarantine_dir = tempdir.path().join("quarantine");
+        let err = fetch_verified(
+            TEST_DIAGNOSTICS,
+            &wrong_release,
+            &[],
+            Some(&cache_dir),
+            Some(&quarantine_dir),
+        )
+        .unwrap_err();
+        let quarantined = quarantine_dir.join(format!(
+            "protoc-34.1-linux-x86_64-{}.zip",
+            versions::format_hash(&release.hash)
+        ));
+        assert_eq!(data.as_slice(), std::fs::read(quarantined).unwrap());
+        assert_eq!("no protoc download sources configured", err.to_string());
+    }
+
+    #[test]
+    fn test_resolve_locked_release() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let version = ProtocVersion::new(99, 0);
+        let platform = Platform::new(OS::Linux, CPUArch::X86_64);
+        let config = Config {
+            project_dir: Some(tempdir.path().to_path_buf()),
+            ..Config::new().version(version).sources(Vec::<String>::new())
+        };
+        assert_eq!(None, config.unknown_version());
+        let config = config.trust_on_first_use(true);
+        assert_eq!(Some(version), config.unknown_version());
+
+        // not recorded: fails to download without any sources
+        let err = config
+            .resolve_locked_release(TEST_DIAGNOSTICS, platform, version)
+            .err()
+            .unwrap();
+        assert_eq!("no protoc download sources configured", err.to_string());
+
+        let data = b"fake protoc zip";
+        let mut lockfile =
+            lockfile::Lockfile::read(&tempdir.path().join(lockfile::LOCKFILE_NAME)).unwrap();
+        lockfile
+            .record(lockfile::LockedRelease {
+                version,
+                platform,
+                hash: protoc_hash(data),
+            })
+            .unwrap();
+        let resolved = config
+            .resolve_locked_release(TEST_DIAGNOSTICS, platform, version)
+            .unwrap();
+        assert_eq!(
+            versions::Release {
+                version,
+                platform,
+                hash: protoc_hash(data),
+            },
+            resolved.release
+        );
+        assert_eq!(Some(lockfile.path().to_path_buf()), resolved.lockfile_path);
+
+        // a cached release with a different hash is not used
+        let cache_dir = tempdir.path().join("cache");
+        write_file(&cache_path(&cache_dir, &resolved.release), b"changed").unwrap();
+        let config = config.cache_dir(&cache_dir);
+        let err = write_protoc(
+            TEST_DIAGNOSTICS,
+            &config,
+            &resolved,
+            &tempdir.path().join("protoc"),
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().starts_with("verifying with ")
+                && err
+                    .to_string()
+                    .ends_with("dlprotoc.lock: no protoc download sources configured"),
+            "unexpected error: {err}"
+        );
+    }
+
+    #[test]
+    fn test_resolve_trusted_hash() {
+        let linux = Platform::new(OS::Linux, CPUArch::X86_64);
+        let config = Config::new()
+            .version(ProtocVersion::new(99, 0))
+            .trusted_hash(ProtocVersion::new(99, 0), linux, [1; 32]);
+        let release = config.resolve_release(linux).unwrap();
+        assert_eq!(ProtocVersion::new(99, 0), release.version);
+        assert_eq!([1; 32], release.hash);
+
+        // a trusted hash overrides the known hash
+        let config = Config::new()
+            .trusted_hash(LATEST_VERSION, linux, [2; 32])
+            .trusted_hash(LATEST_VERSION, linux, [3; 32]);
+        assert_eq!([2; 32], config.resolve_release(linux).unwrap().hash);
+
+        let err = Config::new()
+            .version(ProtocVersion::new(99, 0))
+            .resolve_release(linux)
+            .unwrap_err();
+        assert_eq!(
+            format!(
+                "unknown hash for linux x86_64 99.0: the latest known version for linux-x86_64 is \
+                {LATEST_VERSION}"
+            ),
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_check_installed() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let protoc_path = installed::write_fake_protoc(tempdir.path(), "echo libprotoc 34.0-rc1");
+        let rc = "34.0-rc1".parse::<ProtocVersion>().unwrap();
+
+        assert_eq!(rc, Config::new().check_installed(&protoc_path).unwrap());
+        let config = Config::new().version(">=33".parse::<VersionReq>().unwrap());
+        let err = config.check_installed(&protoc_path).unwrap_err();
+        assert_eq!(ErrorKind::Environment, err.kind());
+        assert_eq!("protoc 34.0-rc1 does not match >=33", err.to_string());
+        let config = config.prereleases(true);
+        assert_eq!(rc, config.check_installed(&protoc_path).unwrap());
+
+        let err = Config::new()
+            .check_installed(&tempdir.path().join("missing"))
+            .unwrap_err();
+        assert_eq!(ErrorKind::Io, err.kind());
+    }
+
+    #[test]
+    fn test_check_system_protoc() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let protoc_path = installed::write_fake_protoc(tempdir.path(), "echo libprotoc 3.21.12");
+
+        let config = Config::new().strategy(Strategy::System);
+        let installation = config
+            .check_system_protoc(Some(protoc_path.clone()))
+            .unwrap();
+        assert_eq!(ProtocVersion::new(21, 12), installation.version());
+        assert_eq!(None, installation.platform());
+        assert_eq!(protoc_path, installation.protoc_path());
+
+        let err = config.check_system_protoc(None).unwrap_err();
+        assert_eq!(ErrorKind::Environment, err.kind());
+        assert_eq!("protoc not found in PATH", err.to_string());
+
+        let err = config
+            .version(ProtocVersion::new(33, 5))
+            .check_system_protoc(Some(protoc_path.clone()))
+            .unwrap_err();
+        assert_eq!(ErrorKind::Environment, err.kind());
+        assert_eq!(
+            format!(
+                "system protoc {}: protoc 21.12 does not match =33.5",
+                protoc_path.display()
+            ),
+            format!("{err:#}")
+        );
+    }
+
+    #[test]
+    fn test_build_inputs_used() {
+        // unset variables and the defaults do not need rerun directives
+        let mut inputs = BuildInputs::default();
+        assert_eq!(None, inputs.env_var("DLPROTOC_TEST_UNSET").unwrap());
+        assert!(!inputs.used);
+        assert_eq!(vec!["DLPROTOC_TEST_UNSET"], inputs.env_vars);
+
+        inputs.add_path(PathBuf::from("protoc-version"));
+        assert!(inputs.used);
+    }
+
+    #[test]
+    fn test_check_advisory() {
+        let info = |severity| {
+            ReleaseInfo::new(
+                ProtocVersion::new(30, 0),
+                Some(ReleaseDate::new(2025, 3, 4)),
+                Some(Advisory {
+                    severity,
+                    reason: "example regression",
+                }),
+            )
+        };
+        assert_eq!(None, check_advisory(None, false).unwrap());
+        assert_eq!(
+            Some(String::from("protoc 30.0: example regression")),
+            check_advisory(Some(&info(Severity::Warning)), false).unwrap()
+        );
+        assert_eq!(
+            Some(String::from(
+                "using known bad protoc 30.0: example regression"
+            )),
+            check_advisory(Some(&info(Severity::KnownBad)), true).unwrap()
+        );
+        let err = check_advisory(Some(&info(Severity::KnownBad)), false).unwrap_err();
+        assert_eq!(ErrorKind::KnownBad, err.kind());
+        assert_eq!(
+            "protoc 30.0 is known bad: example regression; set allow-known-bad to use it anyway",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_check_outdated() {
+        assert_eq!(None, check_outdated(LATEST_VERSION, 0));
+        assert_eq!(None, check_outdated(ProtocVersion::new(33, 5), 100));
+        let warning = check_outdated(ProtocVersion::new(33, 5), 1).unwrap();
+        assert!(
+            warning.starts_with("protoc 33.5 is ")
+                && warning.ends_with(&format!(
+                    " minor releases behind the latest known version {LATEST_VERSION}"
+                )),
+            "unexpected warning: {warning}"
+        );
+    }
+
+    #[test]
+    fn test_select_host_platform() {
+        assert_eq!(
+            Platform::detect().unwrap(),
+            select_host_platform(None, None).unwrap()
+        );
+        assert_eq!(
+            Platform::new(OS::OSX, CPUArch::AArch64),
+            select_host_platform(None, Some("aarch64-apple-darwin")).unwrap()
+        );
+        assert_eq!(
+            Platform::new(OS::OSX, CPUArch::UniversalBinary),
+            select_host_platform(Some("osx-universal_binary"), Some("aarch64-apple-darwin"))
+                .unwrap()
+        );
+        assert_eq!(
+            Platform::new(OS::Linux, CPUArch::X86_64),
+            select_host_platform(Some("x86_64-unknown-linux-musl"), None).unwrap()
+        );
+
+        let err = select_host_platform(None, Some("x86_64-unknown-freebsd")).unwrap_err();
+        assert!(
+            err.to_string().starts_with("env var HOST: "),
+            "unexpected error: {err}"
+        );
+        assert_eq!(ErrorKind::UnsupportedPlatform, err.kind());
+        let err = select_host_platform(Some("bad"), Some("x86_64-apple-darwin")).unwrap_err();
+        assert!(
+            err.to_string().starts_with("env var DLPROTOC_PLATFORM: "),
+            "unexpected error: {err}"
+        );
+    }
+
+    #[test]
+    fn test_parse_version_override() {
+        let platform = Platform::new(OS::Linux, CPUArch::X86_64);
+        let parse = |value: &str, trusted: &[KnownVersion], allow_unknown: bool| {
+            parse_version_override(
+                value,
+                &versions::with_trusted_hashes(trusted),
+                platform,
+                UniversalBinary::Fallback,
+                allow_unknown,
+            )
+        };
+        assert_eq!(
+            VersionReq::exact(LATEST_VERSION),
+            parse(&format!("{LATEST_VERSION}\n"), &[], false).unwrap()
+        );
+        assert_eq!(
+            ">=30".parse::<VersionReq>().unwrap(),
+            parse(">=30", &[], false).unwrap()
+        );
+
+        let err = parse("99.0", &[], false).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("no known protoc version matches =99.0: "),
+            "unexpected error: {err}"
+        );
+        assert_eq!(ErrorKind::UnknownVersion, err.kind());
+        // the same suggestions as Config::version
+        let err = parse("33.1", &[], false).unwrap_err();
+        assert_eq!(
+            format!(
+                "no known protoc version matches =33.1: the latest known version for linux-x86_64 \
+                 is {LATEST_VERSION}; did you mean 33.0 or 33.2?"
+            ),
+            err.to_string()
+        );
+        assert_eq!(
+            VersionReq::exact(ProtocVersion::new(99, 0)),
+            parse("99.0", &[], true).unwrap()
+        );
+        let trusted = KnownVersion::new(ProtocVersion::new(99, 0), platform, [1; 32]);
+        assert_eq!(
+            VersionReq::exact(ProtocVersion::new(99, 0)),
+            parse("99.0", &[trusted], false).unwrap()
+        );
+        let err = parse("latest", &[], true).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("invalid protoc version requirement"),
+            "unexpected error: {err}"
+        );
+    }
+
+    #[test]
+    fn test_verify_hash_and_download_failed() {
+        let release = versions::Release {
+            version: ProtocVersion::new(34, 1),
+            platform: Platform::new(OS::Linux, CPUArch::X86_64),
+            hash: [0; 32],
+        };
+        verify_hash(
+            TEST_DIAGNOSTICS,
+            &versions::Release {
+                hash: protoc_hash(b"data"),
+                ..release
+            },
+            b"data",
+            "url",
+            None,
+        )
+        .unwrap();
+        let err = verify_hash(TEST_DIAGNOSTICS, &release, b"data", "url", None).unwrap_err();
+        assert_eq!(ErrorKind::HashMismatch, err.kind());
+        assert_eq!(
+            format!(
+                "hash mismatch for protoc 34.1 linux-x86_64 from url: expected sha256 {}, got {} (4 bytes)",
+                "00".repeat(32),
+                versions::format_hash(&protoc_hash(b"data"))
+            ),
+            err.to_string()
+        );
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let err = verify_hash(
+            TEST_DIAGNOSTICS,
+            &release,
+            b"data",
+            "url",
+            Some(tempdir.path()),
+        )
+        .unwrap_err();
+        let quarantined = tempdir.path().join(format!(
+            "protoc-34.1-linux-x86_64-{}.zip",
+            versions::format_hash(&protoc_hash(b"data"))
+        ));
+        assert!(
+            err.to_string()
+                .ends_with(&format!("; saved to {}", quarantined.display())),
+            "unexpected error: {err}"
+        );
+        assert_eq!(b"data".as_slice(), std::fs::read(quarantined).unwrap());
+
+        let err = download_failed(vec![
+            (
+                String::from("a"),
+                Error::new(ErrorKind::Network, String::from("failed a")),
+            ),
+            (
+                String::from("b"),
+                Error::new(ErrorKind::Network, String::from("failed b")),
+            ),
+        ]);
+        assert_eq!(ErrorKind::Network, err.kind());
+        assert_eq!(
+            "failed downloading protoc from a: failed b",
+            format!("{err:#}")
+        );
+        assert_eq!(ErrorKind::Offline, download_failed(Vec::new()).kind());
+    }
+
+    #[test]
+    fn test_download_failed_keeps_source() {
+        // nothing listens on the discard port, so this fails without using the network
+        let url = "http://127.0.0.1:9/protoc.zip";
+        let err = download_url(url).unwrap_err();
+        let err = download_failed(vec![(String::from(url), err)]);
+        assert_eq!(ErrorKind::Network, err.kind());
+        let source = std::error::Error::source(&err).unwrap();
+        assert!(source.is::<reqwest::Error>(), "unexpected source: {source}");
+    }
+
     #[test]
     fn test_error_implements_std_error() {
         // ensures we can use this error as a std Error
@@ -0,0 +1,211 @@
+//! Reads and writes `dlprotoc.lock`, which records the hashes of protoc releases that are not
+//! known to this crate the first time they are downloaded.
+
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{Error, ErrorKind},
+    protoc_version::ProtocVersion,
+    versions::{self, Platform},
+};
+
+pub const LOCKFILE_NAME: &str = "dlprotoc.lock";
+
+const HEADER: &str = "# SHA-256 hashes of protoc releases recorded by dlprotoc on first download.
+# Commit this file so every build verifies the same protoc. Do not edit it by hand.
+";
+
+/// A release recorded in the lockfile.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LockedRelease {
+    pub version: ProtocVersion,
+    pub platform: Platform,
+    pub hash: [u8; 32],
+}
+
+/// The contents of a lockfile.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lockfile {
+    path: PathBuf,
+    releases: Vec<LockedRelease>,
+}
+
+impl Lockfile {
+    /// Reads the lockfile at `path`. Returns an empty lockfile if it does not exist.
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let releases = match std::fs::read_to_string(path) {
+            Ok(contents) => parse(&contents).map_err(|e| e.prefixed(path.display().to_string()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(Error::io(format_args!("reading {}", path.display()), e)),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            releases,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the recorded hash for the release, or None if it is not recorded.
+    pub fn find(&self, version: ProtocVersion, platform: Platform) -> Option<[u8; 32]> {
+        self.releases
+            .iter()
+            .find(|locked| locked.version == version && locked.platform == platform)
+            .map(|locked| locked.hash)
+    }
+
+    /// Records a release and writes the lockfile. Returns an error if the release is already
+    /// recorded with a different hash.
+    pub fn record(&mut self, release: LockedRelease) -> Result<(), Error> {
+        if let Some(hash) = self.find(release.version, release.platform) {
+            if hash == release.hash {
+                return Ok(());
+            }
+            return Err(Error::new(
+                ErrorKind::HashMismatch,
+                format!(
+                    "{}: protoc {} {} is already recorded with a different hash",
+                    self.path.display(),
+                    release.version,
+                    release.platform
+                ),
+            ));
+        }
+        self.releases.push(release);
+        self.releases.sort_by(|a, b| {
+            a.version
+                .cmp(&b.version)
+                .then_with(|| a.platform.to_string().cmp(&b.platform.to_string()))
+        });
+        self.write()
+    }
+
+    fn write(&self) -> Result<(), Error> {
+        let mut contents = String::from(HEADER);
+        for release in &self.releases {
+            write!(
+                contents,
+                "\n[[release]]\nversion = \"{}\"\nplatform = \"{}\"\nsha256 = \"{}\"\n",
+                release.version,
+                release.platform,
+                versions::format_hash(&release.hash)
+            )
+            .unwrap();
+        }
+        crate::write_file(&self.path, contents.as_bytes())
+    }
+}
+
+fn parse(contents: &str) -> Result<Vec<LockedRelease>, Error> {
+    let table = contents
+        .parse::<toml::Table>()
+        .map_err(|e| Error::with_source(ErrorKind::Other, String::from("parsing"), e))?;
+    let Some(releases) = table.get("release") else {
+        return Ok(Vec::new());
+    };
+    let releases = releases
+        .as_array()
+        .ok_or_else(|| Error::from_string(String::from("release must be an array of tables")))?;
+
+    releases
+        .iter()
+        .map(|release| {
+            let field = |key: &str| {
+                release
+                    .get(key)
+                    .and_then(toml::Value::as_str)
+                    .ok_or_else(|| Error::from_string(format!("release.{key} must be a string")))
+            };
+            Ok(LockedRelease {
+                version: field("version")?.parse()?,
+                platform: field("platform")?.parse()?,
+                hash: versions::parse_hash(field("sha256")?)?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::versions::{CPUArch, OS};
+
+    #[test]
+    fn test_record_read() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join(LOCKFILE_NAME);
+        let mut lockfile = Lockfile::read(&path).unwrap();
+        let linux = Platform::new(OS::Linux, CPUArch::X86_64);
+        let osx = Platform::new(OS::OSX, CPUArch::AArch64);
+        let version = ProtocVersion::new(99, 0);
+        assert_eq!(None, lockfile.find(version, linux));
+
+        let locked = LockedRelease {
+            version,
+            platform: osx,
+            hash: [0xab; 32],
+        };
+        lockfile.record(locked).unwrap();
+        lockfile
+            .record(LockedRelease {
+                platform: linux,
+                hash: [1; 32],
+                ..locked
+            })
+            .unwrap();
+        // recording the same hash again does nothing
+        lockfile.record(locked).unwrap();
+
+        let read = Lockfile::read(&path).unwrap();
+        assert_eq!(lockfile, read);
+        assert_eq!(Some([0xab; 32]), read.find(version, osx));
+        assert_eq!(Some([1; 32]), read.find(version, linux));
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            contents.contains(&format!("sha256 = \"{}\"", "ab".repeat(32))),
+            "unexpected contents: {contents}"
+        );
+
+        let err = lockfile
+            .record(LockedRelease {
+                hash: [2; 32],
+                ..locked
+            })
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("protoc 99.0 osx-aarch_64 is already recorded with a different hash"),
+            "unexpected error: {err}"
+        );
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join(LOCKFILE_NAME);
+        for (contents, expected) in [
+            ("release = 1\n", "release must be an array of tables"),
+            (
+                "[[release]]\nversion = \"99.0\"\nplatform = \"linux-x86_64\"\n",
+                "release.sha256 must be a string",
+            ),
+            (
+                "[[release]]\nversion = \"99.0\"\nplatform = \"linux-x86_64\"\nsha256 = \"abc\"\n",
+                "invalid sha256: \"abc\"",
+            ),
+            ("[[release]\n", "parsing: "),
+        ] {
+            std::fs::write(&path, contents).unwrap();
+            let err = Lockfile::read(&path).unwrap_err();
+            assert!(
+                format!("{err:#}").contains(expected),
+                "expected error containing {expected:?}: {err}"
+            );
+        }
+    }
+}
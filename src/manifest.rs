@@ -0,0 +1,548 @@
+//! Reads dlprotoc settings from the `metadata.dlprotoc` table in Cargo.toml.
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    error::{Error, ErrorKind},
+    installed::Strategy,
+    protoc_version::VersionReq,
+    versions::{self, KnownVersion, UniversalBinary},
+};
+
+const MANIFEST_FILE_NAME: &str = "Cargo.toml";
+const METADATA_TABLE: &str = "dlprotoc";
+
+/// Settings from a `[package.metadata.dlprotoc]` or `[workspace.metadata.dlprotoc]` table. Each
+/// setting is None if it is not set.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Settings {
+    pub version: Option<VersionReq>,
+    pub sources: Option<Vec<String>>,
+    pub cache_dir: Option<PathBuf>,
+    pub quarantine_dir: Option<PathBuf>,
+    pub universal_binary: Option<UniversalBinary>,
+    pub prereleases: Option<bool>,
+    pub trust_on_first_use: Option<bool>,
+    pub trusted_hashes: Option<Vec<KnownVersion>>,
+    pub allow_known_bad: Option<bool>,
+    pub warn_outdated: Option<u32>,
+    pub use_existing_protoc: Option<bool>,
+    pub strategy: Option<Strategy>,
+}
+
+impl Settings {
+    /// Returns settings where values in `self` override values in `defaults`.
+    fn or(self, defaults: Self) -> Self {
+        Self {
+            version: self.version.or(defaults.version),
+            sources: self.sources.or(defaults.sources),
+            cache_dir: self.cache_dir.or(defaults.cache_dir),
+            quarantine_dir: self.quarantine_dir.or(defaults.quarantine_dir),
+            universal_binary: self.universal_binary.or(defaults.universal_binary),
+            prereleases: self.prereleases.or(defaults.prereleases),
+            trust_on_first_use: self.trust_on_first_use.or(defaults.trust_on_first_use),
+            trusted_hashes: self.trusted_hashes.or(defaults.trusted_hashes),
+            allow_known_bad: self.allow_known_bad.or(defaults.allow_known_bad),
+            warn_outdated: self.warn_outdated.or(defaults.warn_outdated),
+            use_existing_protoc: self.use_existing_protoc.or(defaults.use_existing_protoc),
+            strategy: self.strategy.or(defaults.strategy),
+        }
+    }
+}
+
+/// Settings loaded from the manifests of a package and its workspace.
+#[derive(Clone, Debug, Default)]
+pub struct LoadedSettings {
+    pub settings: Settings,
+    /// The manifest files that were read, so the build script can rerun when they change.
+    pub paths: Vec<PathBuf>,
+    /// True if any of the manifests has a dlprotoc table.
+    pub found: bool,
+}
+
+/// Reads the settings for the package in `manifest_dir`. Package settings override workspace
+/// settings.
+pub fn load(manifest_dir: &Path) -> Result<LoadedSettings, Error> {
+    let package_path = manifest_dir.join(MANIFEST_FILE_NAME);
+    let package_manifest = read_manifest(&package_path)?;
+    let package_settings =
+        settings_from_manifest(&package_manifest, "package", manifest_dir, &package_path)?;
+
+    let mut found = has_table(&package_manifest, "package");
+    let mut paths = vec![package_path.clone()];
+    let workspace_settings = if package_manifest.contains_key("workspace") {
+        found |= has_table(&package_manifest, "workspace");
+        settings_from_manifest(&package_manifest, "workspace", manifest_dir, &package_path)?
+    } else if let Some((workspace_dir, workspace_manifest)) =
+        find_workspace(manifest_dir, &package_manifest)?
+    {
+        let workspace_path = workspace_dir.join(MANIFEST_FILE_NAME);
+        found |= has_table(&workspace_manifest, "workspace");
+        paths.push(workspace_path.clone());
+        settings_from_manifest(
+            &workspace_manifest,
+            "workspace",
+            &workspace_dir,
+            &workspace_path,
+        )?
+    } else {
+        Settings::default()
+    };
+
+    Ok(LoadedSettings {
+        settings: package_settings.or(workspace_settings),
+        paths,
+        found,
+    })
+}
+
+fn read_manifest(path: &Path) -> Result<toml::Table, Error> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| Error::io(format_args!("reading {}", path.display()), e))?;
+    contents
+        .parse::<toml::Table>()
+        .map_err(|e| Error::with_source(ErrorKind::Other, format!("parsing {}", path.display()), e))
+}
+
+/// Returns the directory and manifest of the workspace root, or None if the package is not part
+/// of a workspace. Uses `package.workspace` if set. Otherwise searches the parent directories for
+/// a workspace that has the package as a member, skipping manifests that cannot be read.
+fn find_workspace(
+    manifest_dir: &Path,
+    package_manifest: &toml::Table,
+) -> Result<Option<(PathBuf, toml::Table)>, Error> {
+    let explicit_workspace = package_manifest
+        .get("package")
+        .and_then(|package| package.get("workspace"))
+        .and_then(toml::Value::as_str);
+    if let Some(workspace) = explicit_workspace {
+        let workspace_dir = manifest_dir.join(workspace);
+        let workspace_manifest = read_manifest(&workspace_dir.join(MANIFEST_FILE_NAME))?;
+        return Ok(Some((workspace_dir, workspace_manifest)));
+    }
+
+    for dir in manifest_dir.ancestors().skip(1) {
+        let path = dir.join(MANIFEST_FILE_NAME);
+        if !path.is_file() {
+            continue;
+        }
+        let Ok(manifest) = read_manifest(&path) else {
+            continue;
+        };
+        if let Some(workspace) = manifest.get("workspace")
+            && is_member(workspace, dir, manifest_dir)
+        {
+            return Ok(Some((dir.to_path_buf(), manifest)));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns true if the package in `manifest_dir` matches the `members` of the `[workspace]` table
+/// in `workspace_dir`, and is not in its `exclude` list. Members may use `*` and `?` wildcards.
+fn is_member(workspace: &toml::Value, workspace_dir: &Path, manifest_dir: &Path) -> bool {
+    let Ok(relative) = manifest_dir.strip_prefix(workspace_dir) else {
+        return false;
+    };
+    let paths = |key: &str| {
+        workspace
+            .get(key)
+            .and_then(toml::Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(toml::Value::as_str)
+            .map(Path::new)
+    };
+    if paths("exclude").any(|exclude| relative.starts_with(exclude)) {
+        return false;
+    }
+    paths("members").any(|member| path_matches(member, relative))
+}
+
+/// Returns true if `path` matches `pattern`, where each component of `pattern` may use `*` and `?`
+/// wildcards.
+fn path_matches(pattern: &Path, path: &Path) -> bool {
+    let normal = |path: &Path| {
+        path.components()
+            .filter(|component| !matches!(component, std::path::Component::CurDir))
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+    };
+    let pattern = normal(pattern);
+    let path = normal(path);
+    pattern.len() == path.len()
+        && pattern
+            .iter()
+            .zip(&path)
+            .all(|(pattern, name)| wildcard_matches(pattern.as_bytes(), name.as_bytes()))
+}
+
+/// Returns true if `name` matches `pattern`, where `*` matches any sequence and `?` matches any
+/// single byte.
+fn wildcard_matches(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => {
+            wildcard_matches(rest, name)
+                || (!name.is_empty() && wildcard_matches(pattern, &name[1..]))
+        }
+        (Some((b'?', rest)), Some((_, name_rest))) => wildcard_matches(rest, name_rest),
+        (Some((expected, rest)), Some((actual, name_rest))) => {
+            expected == actual && wildcard_matches(rest, name_rest)
+        }
+        _ => false,
+    }
+}
+
+/// Returns true if the manifest has a `[{section}.metadata.dlprotoc]` table.
+fn has_table(manifest: &toml::Table, section: &str) -> bool {
+    manifest
+        .get(section)
+        .and_then(|section| section.get("metadata"))
+        .and_then(|metadata| metadata.get(METADATA_TABLE))
+        .is_some()
+}
+
+/// Returns the settings in the `[{section}.metadata.dlprotoc]` table. Relative paths are relative
+/// to `manifest_dir`. Errors include `manifest_path`.
+fn settings_from_manifest(
+    manifest: &toml::Table,
+    section: &str,
+    manifest_dir: &Path,
+    manifest_path: &Path,
+) -> Result<Settings, Error> {
+    parse_settings(manifest, section, manifest_dir)
+        .map_err(|e| e.prefixed(manifest_path.display().to_string()))
+}
+
+fn parse_settings(
+    manifest: &toml::Table,
+    section: &str,
+    manifest_dir: &Path,
+) -> Result<Settings, Error> {
+    let table = manifest
+        .get(section)
+        .and_then(|section| section.get("metadata"))
+        .and_then(|metadata| metadata.get(METADATA_TABLE));
+    let Some(table) = table else {
+        return Ok(Settings::default());
+    };
+    let Some(table) = table.as_table() else {
+        return Err(Error::from_string(format!(
+            "{section}.metadata.{METADATA_TABLE} must be a table"
+        )));
+    };
+
+    let mut settings = Settings::default();
+    for (key, value) in table {
+        let key_error = |message: &str| {
+            Error::from_string(format!(
+                "{section}.metadata.{METADATA_TABLE}.{key}: {message}"
+            ))
+        };
+        let as_str = || value.as_str().ok_or_else(|| key_error("must be a string"));
+        let as_bool = || {
+            value
+                .as_bool()
+                .ok_or_else(|| key_error("must be a boolean"))
+        };
+
+        match key.as_str() {
+            "version" => {
+                let version = as_str()?
+                    .parse::<VersionReq>()
+                    .map_err(|e| key_error(&e.to_string()))?;
+                settings.version = Some(version);
+            }
+            "sources" => {
+                let sources = value
+                    .as_array()
+                    .and_then(|sources| {
+                        sources
+                            .iter()
+                            .map(|source| source.as_str().map(String::from))
+                            .collect::<Option<Vec<_>>>()
+                    })
+                    .ok_or_else(|| key_error("must be an array of strings"))?;
+                settings.sources = Some(sources);
+            }
+            "cache-dir" => {
+                settings.cache_dir = Some(manifest_dir.join(as_str()?));
+            }
+            "quarantine-dir" => {
+                settings.quarantine_dir = Some(manifest_dir.join(as_str()?));
+            }
+            "universal-binary" => {
+                let universal_binary = as_str()?
+                    .parse::<UniversalBinary>()
+                    .map_err(|e| key_error(&e.to_string()))?;
+                settings.universal_binary = Some(universal_binary);
+            }
+            "prereleases" => settings.prereleases = Some(as_bool()?),
+            "trust-on-first-use" => settings.trust_on_first_use = Some(as_bool()?),
+            "allow-known-bad" => settings.allow_known_bad = Some(as_bool()?),
+            "use-existing-protoc" => settings.use_existing_protoc = Some(as_bool()?),
+            "strategy" => {
+                let strategy = as_str()?
+                    .parse::<Strategy>()
+                    .map_err(|e| key_error(&e.to_string()))?;
+                settings.strategy = Some(strategy);
+            }
+            "warn-outdated" => {
+                let warn_outdated = value
+                    .as_integer()
+                    .and_then(|n| u32::try_from(n).ok())
+                    .ok_or_else(|| key_error("must be a non-negative integer"))?;
+                settings.warn_outdated = Some(warn_outdated);
+            }
+            "trusted-hashes" => {
+                let trusted_hashes = value
+                    .as_array()
+                    .ok_or_else(|| key_error("must be an array of tables"))?
+                    .iter()
+                    .map(parse_trusted_hash)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| key_error(&e.to_string()))?;
+                settings.trusted_hashes = Some(trusted_hashes);
+            }
+            _ => return Err(key_error("unknown setting")),
+        }
+    }
+    Ok(settings)
+}
+
+/// Parses a `{ version = "...", platform = "...", sha256 = "..." }` table.
+fn parse_trusted_hash(value: &toml::Value) -> Result<KnownVersion, Error> {
+    let field = |key: &str| {
+        value
+            .get(key)
+            .and_then(toml::Value::as_str)
+            .ok_or_else(|| Error::from_string(format!("{key} must be a string")))
+    };
+    Ok(KnownVersion::new(
+        field("version")?.parse()?,
+        field("platform")?.parse()?,
+        versions::parse_hash(field("sha256")?)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        protoc_version::ProtocVersion,
+        versions::{CPUArch, OS, Platform},
+    };
+
+    fn write_manifest(dir: &Path, contents: &str) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join(MANIFEST_FILE_NAME), contents).unwrap();
+    }
+
+    #[test]
+    fn test_load_package_settings() {
+        let tempdir = tempfile::tempdir().unwrap();
+        write_manifest(
+            tempdir.path(),
+            r#"[package]
+name = "example"
+
+[package.metadata.dlprotoc]
+version = ">=31"
+sources = ["https://mirror.example.com/protobuf"]
+cache-dir = "protoc-cache"
+quarantine-dir = "protoc-quarantine"
+universal-binary = "prefer"
+prereleases = true
+trust-on-first-use = true
+allow-known-bad = true
+use-existing-protoc = true
+strategy = "system-then-download"
+warn-outdated = 2
+trusted-hashes = [
+    { version = "99.0", platform = "linux-x86_64", sha256 = "0101010101010101010101010101010101010101010101010101010101010101" },
+]
+"#,
+        );
+
+        let loaded = load(tempdir.path()).unwrap();
+        assert_eq!(
+            Settings {
+                version: Some(">=31".parse().unwrap()),
+                sources: Some(vec![String::from("https://mirror.example.com/protobuf")]),
+                cache_dir: Some(tempdir.path().join("protoc-cache")),
+                quarantine_dir: Some(tempdir.path().join("protoc-quarantine")),
+                universal_binary: Some(UniversalBinary::Prefer),
+                prereleases: Some(true),
+                trust_on_first_use: Some(true),
+                trusted_hashes: Some(vec![KnownVersion::new(
+                    ProtocVersion::new(99, 0),
+                    Platform::new(OS::Linux, CPUArch::X86_64),
+                    [1; 32]
+                )]),
+                allow_known_bad: Some(true),
+                use_existing_protoc: Some(true),
+                strategy: Some(Strategy::SystemThenDownload),
+                warn_outdated: Some(2),
+            },
+            loaded.settings
+        );
+        assert_eq!(vec![tempdir.path().join(MANIFEST_FILE_NAME)], loaded.paths);
+        assert!(loaded.found);
+
+        write_manifest(tempdir.path(), "[package]\nname = \"example\"\n");
+        assert!(!load(tempdir.path()).unwrap().found);
+    }
+
+    #[test]
+    fn test_load_workspace_settings() {
+        let tempdir = tempfile::tempdir().unwrap();
+        write_manifest(
+            tempdir.path(),
+            r#"[workspace]
+members = ["crates/*"]
+
+[workspace.metadata.dlprotoc]
+version = "33.5"
+cache-dir = "target/protoc"
+"#,
+        );
+        let package_dir = tempdir.path().join("crates").join("example");
+        write_manifest(
+            &package_dir,
+            r#"[package]
+name = "example"
+
+[package.metadata.dlprotoc]
+cache-dir = "cache"
+"#,
+        );
+        let other_dir = tempdir.path().join("crates").join("other");
+        write_manifest(&other_dir, "[package]\nname = \"other\"\n");
+
+        // the package setting overrides the workspace setting
+        let loaded = load(&package_dir).unwrap();
+        assert_eq!(
+            Settings {
+                version: Some(ProtocVersion::new(33, 5).into()),
+                cache_dir: Some(package_dir.join("cache")),
+                ..Settings::default()
+            },
+            loaded.settings
+        );
+        assert_eq!(
+            vec![
+                package_dir.join(MANIFEST_FILE_NAME),
+                tempdir.path().join(MANIFEST_FILE_NAME)
+            ],
+            loaded.paths
+        );
+
+        // workspace paths are relative to the workspace
+        let loaded = load(&other_dir).unwrap();
+        assert_eq!(
+            Some(tempdir.path().join("target/protoc")),
+            loaded.settings.cache_dir
+        );
+        assert!(loaded.found);
+    }
+
+    #[test]
+    fn test_load_workspace_members() {
+        let tempdir = tempfile::tempdir().unwrap();
+        write_manifest(
+            tempdir.path(),
+            r#"[workspace]
+members = ["crates/*", "./tool"]
+exclude = ["crates/excluded"]
+
+[workspace.metadata.dlprotoc]
+trust-on-first-use = true
+"#,
+        );
+        let package = "[package]\nname = \"example\"\n";
+        for (dir, is_member) in [
+            ("crates/example", true),
+            ("tool", true),
+            ("crates/excluded", false),
+            ("vendor/example", false),
+            ("crates/example/nested", false),
+        ] {
+            let package_dir = tempdir.path().join(dir);
+            write_manifest(&package_dir, package);
+            let loaded = load(&package_dir).unwrap();
+            let expected = is_member.then_some(true);
+            assert_eq!(expected, loaded.settings.trust_on_first_use, "{dir}");
+            assert_eq!(is_member, loaded.found, "{dir}");
+        }
+
+        // manifests in parent directories that cannot be parsed are skipped
+        let package_dir = tempdir.path().join("broken").join("example");
+        write_manifest(&tempdir.path().join("broken"), "[workspace");
+        write_manifest(&package_dir, package);
+        assert!(!load(&package_dir).unwrap().found);
+    }
+
+    #[test]
+    fn test_wildcard_matches() {
+        for (pattern, name, expected) in [
+            ("*", "example", true),
+            ("ex*", "example", true),
+            ("*ple", "example", true),
+            ("e?ample", "example", true),
+            ("e*x", "example", false),
+            ("example", "examples", false),
+            ("?", "", false),
+        ] {
+            assert_eq!(
+                expected,
+                wildcard_matches(pattern.as_bytes(), name.as_bytes()),
+                "{pattern} {name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let tempdir = tempfile::tempdir().unwrap();
+        for (contents, expected) in [
+            (
+                "[package.metadata.dlprotoc]\nversoin = \"33.5\"\n",
+                "package.metadata.dlprotoc.versoin: unknown setting",
+            ),
+            (
+                "[package.metadata.dlprotoc]\nversion = 33\n",
+                "package.metadata.dlprotoc.version: must be a string",
+            ),
+            (
+                "[package.metadata.dlprotoc]\nversion = \"latest\"\n",
+                "package.metadata.dlprotoc.version: invalid protoc version requirement",
+            ),
+            (
+                "[package.metadata.dlprotoc]\nsources = \"https://example.com\"\n",
+                "package.metadata.dlprotoc.sources: must be an array of strings",
+            ),
+            (
+                "[package.metadata.dlprotoc]\nprereleases = \"yes\"\n",
+                "package.metadata.dlprotoc.prereleases: must be a boolean",
+            ),
+            (
+                "[package.metadata.dlprotoc]\ntrusted-hashes = [{ version = \"99.0\" }]\n",
+                "package.metadata.dlprotoc.trusted-hashes: platform must be a string",
+            ),
+            (
+                "[package.metadata.dlprotoc]\nwarn-outdated = -1\n",
+                "package.metadata.dlprotoc.warn-outdated: must be a non-negative integer",
+            ),
+            ("[package.metadata]\ndlprotoc = 1\n", "must be a table"),
+            ("[package", "parsing "),
+        ] {
+            write_manifest(tempdir.path(), contents);
+            let err = load(tempdir.path()).unwrap_err();
+            assert!(
+                err.to_string().contains(expected),
+                "expected error containing {expected:?}: {err}"
+            );
+        }
+    }
+}
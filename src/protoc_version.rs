@@ -0,0 +1,707 @@
+use crate::error::Error;
+use std::{cmp::Ordering, fmt::Display, str::FromStr};
+
+/// A protoc release version, such as "27.0", "3.20.3" or "34.0-rc1". The Display trait returns
+/// the version used in protoc release tags, which is also accepted by [`FromStr`].
+///
+/// protoc 3.21.x was released with the tag v21.x, and later releases dropped the "3." prefix.
+/// [`FromStr`] parses versions starting with 3.21 or later as the tag version, so "3.21.12" is
+/// the same as "21.12".
+///
+/// Versions are ordered by their numeric components, so "27.10" is greater than "27.9". A
+/// prerelease is less than the release with the same numbers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ProtocVersion {
+    pub major: u32,
+    pub minor: u32,
+    /// The third version component, if present (e.g. "3.20.3").
+    pub patch: Option<u32>,
+    /// The release candidate number for prereleases (e.g. 1 for "34.0-rc1").
+    pub prerelease: Option<u32>,
+}
+
+impl ProtocVersion {
+    /// Returns the version major.minor, such as `ProtocVersion::new(27, 0)` for "27.0".
+    #[must_use]
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch: None,
+            prerelease: None,
+        }
+    }
+
+    /// Returns the version used in release file names, which differs from the tag for prereleases:
+    /// the file for the tag "34.0-rc1" is named "protoc-34.0-rc-1-...".
+    #[must_use]
+    pub fn file_version(&self) -> String {
+        match self.prerelease {
+            Some(prerelease) => {
+                let release = Self {
+                    prerelease: None,
+                    ..*self
+                };
+                format!("{release}-rc-{prerelease}")
+            }
+            None => self.to_string(),
+        }
+    }
+
+    /// Returns true if this is a release candidate.
+    #[must_use]
+    pub const fn is_prerelease(&self) -> bool {
+        self.prerelease.is_some()
+    }
+
+    /// Returns a Rust expression that constructs this version, as used in code.
+    #[must_use]
+    pub fn rust_expression(&self) -> String {
+        let mut expression = format!("ProtocVersion::new({}, {})", self.major, self.minor);
+        if self.patch.is_some() || self.prerelease.is_some() {
+            expression = format!(
+                "ProtocVersion {{ patch: {:?}, prerelease: {:?}, ..{expression} }}",
+                self.patch, self.prerelease
+            );
+        }
+        expression
+    }
+
+    /// Returns the key used to order versions. A missing patch is ordered as zero, and a release is
+    /// greater than all its prereleases.
+    const fn order_key(&self) -> (u32, u32, u32, bool, Option<u32>) {
+        let patch = match self.patch {
+            Some(patch) => patch,
+            None => 0,
+        };
+        (
+            self.major,
+            self.minor,
+            patch,
+            self.prerelease.is_none(),
+            self.prerelease,
+        )
+    }
+}
+
+impl Ord for ProtocVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.order_key()
+            .cmp(&other.order_key())
+            // "27.0" and "27.0.0" are ordered the same but are not equal
+            .then_with(|| self.patch.cmp(&other.patch))
+    }
+}
+
+impl PartialOrd for ProtocVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for ProtocVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)?;
+        if let Some(patch) = self.patch {
+            write!(f, ".{patch}")?;
+        }
+        if let Some(prerelease) = self.prerelease {
+            write!(f, "-rc{prerelease}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses major.minor[.patch][-rcN], with an optional "v" prefix as used in release tags. The
+/// protoc file name form of prereleases ("-rc-N") is also accepted.
+impl FromStr for ProtocVersion {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::from_string(format!("invalid protoc version: {s:?}"));
+        let parse_number = |part: &str| {
+            if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            part.parse::<u32>().map_err(|_| invalid())
+        };
+
+        let version = s.strip_prefix('v').unwrap_or(s);
+        let (numbers, prerelease) = match version.split_once("-rc") {
+            Some((numbers, prerelease)) => {
+                let prerelease = prerelease.strip_prefix('-').unwrap_or(prerelease);
+                (numbers, Some(parse_number(prerelease)?))
+            }
+            None => (version, None),
+        };
+
+        let parts = numbers.split('.').collect::<Vec<_>>();
+        let (major, minor, patch) = match parts.as_slice() {
+            [major, minor] => (parse_number(major)?, parse_number(minor)?, None),
+            [major, minor, patch] => (
+                parse_number(major)?,
+                parse_number(minor)?,
+                Some(parse_number(patch)?),
+            ),
+            _ => return Err(invalid()),
+        };
+        // 3.21 without a patch number is not a release
+        let (major, Some(minor), patch) = drop_legacy_major(major, Some(minor), patch) else {
+            return Err(invalid());
+        };
+        Ok(Self {
+            major,
+            minor,
+            patch,
+            prerelease,
+        })
+    }
+}
+
+/// Returns the release tag version for versions in the old 3.x form: 3.21.x and later were
+/// released as 21.x. The minor and patch numbers may be None for partial versions.
+const fn drop_legacy_major(
+    major: u32,
+    minor: Option<u32>,
+    patch: Option<u32>,
+) -> (u32, Option<u32>, Option<u32>) {
+    const LEGACY_MAJOR: u32 = 3;
+    const FIRST_TAG_MAJOR: u32 = 21;
+    match minor {
+        Some(minor) if major == LEGACY_MAJOR && minor >= FIRST_TAG_MAJOR => (minor, patch, None),
+        _ => (major, minor, patch),
+    }
+}
+
+/// A requirement that matches a range of protoc versions, such as ">=31", "~33" or ">=30, <34".
+/// The Display trait returns the requirement in the format accepted by [`FromStr`].
+///
+/// A requirement is a comma-separated list of comparators, which must all match. Each comparator
+/// is an operator followed by a version, where the minor and patch numbers may be omitted:
+///
+/// * `=33.1` or `33.1`: exactly 33.1 (a bare version is exact, unlike Cargo). `=33` matches any 33.x.
+/// * `>33.1`, `>=33.1`, `<33.1`, `<=33.1`: ordered comparisons. `>33` means `>=34.0`.
+/// * `~33.1`: at least 33.1, with the same major and minor (`>=33.1, <33.2`). `~33` means
+///   `>=33.0, <34.0`.
+/// * `^33.1`: at least 33.1, with the same major (`>=33.1, <34.0`).
+/// * `*`: any version.
+///
+/// Prereleases only match if a comparator names a prerelease, or with
+/// [`VersionReq::matches_prerelease`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VersionReq {
+    comparators: Vec<Comparator>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Op {
+    Exact,
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+    Tilde,
+    Caret,
+}
+
+impl Op {
+    const fn symbol(self) -> &'static str {
+        match self {
+            Self::Exact => "=",
+            Self::Greater => ">",
+            Self::GreaterEq => ">=",
+            Self::Less => "<",
+            Self::LessEq => "<=",
+            Self::Tilde => "~",
+            Self::Caret => "^",
+        }
+    }
+}
+
+/// An operator with a possibly partial version.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct Comparator {
+    op: Op,
+    major: u32,
+    minor: Option<u32>,
+    patch: Option<u32>,
+    prerelease: Option<u32>,
+}
+
+impl Comparator {
+    /// Returns the smallest version matching all the specified components.
+    const fn lowest(&self) -> ProtocVersion {
+        ProtocVersion {
+            major: self.major,
+            minor: match self.minor {
+                Some(minor) => minor,
+                None => 0,
+            },
+            patch: self.patch,
+            prerelease: self.prerelease,
+        }
+    }
+
+    /// Returns the smallest version greater than all versions matching the specified components.
+    /// Returns None if all components are specified.
+    const fn next(&self) -> Option<ProtocVersion> {
+        match (self.minor, self.patch) {
+            (None, _) => Some(ProtocVersion::new(self.major.saturating_add(1), 0)),
+            (Some(minor), None) => Some(ProtocVersion::new(self.major, minor.saturating_add(1))),
+            (Some(_), Some(_)) => None,
+        }
+    }
+
+    /// Returns the first version with the next major version.
+    const fn next_major(&self) -> ProtocVersion {
+        ProtocVersion::new(self.major.saturating_add(1), 0)
+    }
+
+    fn matches_exact(&self, version: ProtocVersion) -> bool {
+        version.major == self.major
+            && self.minor.is_none_or(|minor| version.minor == minor)
+            && self
+                .patch
+                .is_none_or(|patch| version.patch.unwrap_or(0) == patch)
+            && self
+                .prerelease
+                .is_none_or(|prerelease| version.prerelease == Some(prerelease))
+    }
+
+    fn matches(&self, version: ProtocVersion) -> bool {
+        let lowest = self.lowest();
+        match self.op {
+            Op::Exact => self.matches_exact(version),
+            Op::Greater => match self.next() {
+                Some(next) => version >= first_prerelease(next),
+                None => version > lowest && !self.matches_exact(version),
+            },
+            Op::GreaterEq => version >= lowest,
+            Op::Less => version < lowest && !self.matches_exact(version),
+            Op::LessEq => match self.next() {
+                Some(next) => version < first_prerelease(next),
+                None => version <= lowest || self.matches_exact(version),
+            },
+            Op::Tilde => {
+                let next = match self.minor {
+                    Some(minor) => ProtocVersion::new(self.major, minor.saturating_add(1)),
+                    None => self.next_major(),
+                };
+                version >= lowest && version < first_prerelease(next)
+            }
+            Op::Caret => version >= lowest && version < first_prerelease(self.next_major()),
+        }
+    }
+}
+
+/// Returns a version that sorts before all prereleases of `version`, so an upper bound of
+/// `version` excludes its release candidates.
+const fn first_prerelease(version: ProtocVersion) -> ProtocVersion {
+    ProtocVersion {
+        prerelease: Some(0),
+        ..version
+    }
+}
+
+impl Display for Comparator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.op.symbol(), self.major)?;
+        if let Some(minor) = self.minor {
+            write!(f, ".{minor}")?;
+        }
+        if let Some(patch) = self.patch {
+            write!(f, ".{patch}")?;
+        }
+        if let Some(prerelease) = self.prerelease {
+            write!(f, "-rc{prerelease}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Comparator {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // check the two character operators first
+        let (op, version) = [
+            (">=", Op::GreaterEq),
+            ("<=", Op::LessEq),
+            ("=", Op::Exact),
+            (">", Op::Greater),
+            ("<", Op::Less),
+            ("~", Op::Tilde),
+            ("^", Op::Caret),
+        ]
+        .into_iter()
+        .find_map(|(symbol, op)| s.strip_prefix(symbol).map(|version| (op, version)))
+        .unwrap_or((Op::Exact, s));
+        let version = version.trim();
+
+        // parse a partial version by filling in the missing components
+        let invalid = || Error::from_string(format!("invalid protoc version requirement: {s:?}"));
+        let (numbers, suffix) = match version.find('-') {
+            Some(index) => version.split_at(index),
+            None => (version, ""),
+        };
+        let (major, minor, patch) = match numbers.split('.').collect::<Vec<_>>().as_slice() {
+            [major] => (major.parse().map_err(|_| invalid())?, None, None),
+            [legacy_major, legacy_minor] if *legacy_major == "3" => {
+                // a partial 3.x version such as ~3.21 may be a tag major version
+                let legacy_minor = legacy_minor.parse().map_err(|_| invalid())?;
+                drop_legacy_major(3, Some(legacy_minor), None)
+            }
+            [_, _] | [_, _, _] => {
+                let parsed = format!("{numbers}{suffix}")
+                    .parse::<ProtocVersion>()
+                    .map_err(|_| invalid())?;
+                let comparator = Self {
+                    op,
+                    major: parsed.major,
+                    minor: Some(parsed.minor),
+                    patch: parsed.patch,
+                    prerelease: parsed.prerelease,
+                };
+                return Ok(comparator);
+            }
+            _ => return Err(invalid()),
+        };
+        if !suffix.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            op,
+            major,
+            minor,
+            patch,
+            prerelease: None,
+        })
+    }
+}
+
+impl VersionReq {
+    /// Returns a requirement that matches any version.
+    #[must_use]
+    pub const fn any() -> Self {
+        Self {
+            comparators: Vec::new(),
+        }
+    }
+
+    /// Returns a requirement that only matches `version`.
+    #[must_use]
+    pub fn exact(version: ProtocVersion) -> Self {
+        Self {
+            comparators: vec![Comparator {
+                op: Op::Exact,
+                major: version.major,
+                minor: Some(version.minor),
+                patch: version.patch,
+                prerelease: version.prerelease,
+            }],
+        }
+    }
+
+    /// Returns true if `version` satisfies this requirement. Prereleases only match if a
+    /// comparator names a prerelease with the same major and minor version.
+    #[must_use]
+    pub fn matches(&self, version: ProtocVersion) -> bool {
+        if version.is_prerelease()
+            && !self.comparators.iter().any(|comparator| {
+                comparator.prerelease.is_some()
+                    && comparator.major == version.major
+                    && comparator.minor == Some(version.minor)
+            })
+        {
+            return false;
+        }
+        self.matches_prerelease(version)
+    }
+
+    /// Returns true if `version` satisfies this requirement, including any prerelease in the
+    /// range. For example, ">=33" matches "34.0-rc1".
+    #[must_use]
+    pub fn matches_prerelease(&self, version: ProtocVersion) -> bool {
+        self.comparators
+            .iter()
+            .all(|comparator| comparator.matches(version))
+    }
+
+    /// Returns the version if this requirement only matches a single fully-specified version.
+    #[must_use]
+    pub fn exact_version(&self) -> Option<ProtocVersion> {
+        match self.comparators.as_slice() {
+            [comparator] if comparator.op == Op::Exact => {
+                comparator.minor.map(|minor| ProtocVersion {
+                    major: comparator.major,
+                    minor,
+                    patch: comparator.patch,
+                    prerelease: comparator.prerelease,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for VersionReq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.comparators.is_empty() {
+            return write!(f, "*");
+        }
+        for (i, comparator) in self.comparators.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{comparator}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for VersionReq {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim() == "*" {
+            return Ok(Self::any());
+        }
+        let comparators = s
+            .split(',')
+            .map(|comparator| comparator.trim().parse())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { comparators })
+    }
+}
+
+impl From<ProtocVersion> for VersionReq {
+    fn from(version: ProtocVersion) -> Self {
+        Self::exact(version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_display() {
+        let tests = [
+            ("27.0", ProtocVersion::new(27, 0)),
+            ("27.10", ProtocVersion::new(27, 10)),
+            (
+                "3.20.3",
+                ProtocVersion {
+                    patch: Some(3),
+                    ..ProtocVersion::new(3, 20)
+                },
+            ),
+            ("21.12", ProtocVersion::new(21, 12)),
+            (
+                "34.0-rc1",
+                ProtocVersion {
+                    prerelease: Some(1),
+                    ..ProtocVersion::new(34, 0)
+                },
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(expected, input.parse().unwrap());
+            assert_eq!(input, expected.to_string());
+        }
+
+        assert_eq!(ProtocVersion::new(27, 0), "v27.0".parse().unwrap());
+        // 3.21.x was released as 21.x
+        assert_eq!(ProtocVersion::new(21, 12), "3.21.12".parse().unwrap());
+        assert_eq!(ProtocVersion::new(22, 0), "3.22.0".parse().unwrap());
+        assert_eq!(
+            "34.0-rc2".parse::<ProtocVersion>().unwrap(),
+            "34.0-rc-2".parse().unwrap()
+        );
+
+        for invalid in [
+            "",
+            "27",
+            "27.",
+            "27.x",
+            "27.0.1.2",
+            "+27.0",
+            "27.0-rc",
+            "27.0-beta",
+            "3.21",
+        ] {
+            let err = invalid.parse::<ProtocVersion>().unwrap_err();
+            assert!(
+                err.to_string().starts_with("invalid protoc version: "),
+                "unexpected error for {invalid:?}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_ordering() {
+        let ordered = [
+            "3.19.6", "3.20.3", "21.12", "27.0-rc1", "27.0-rc2", "27.0", "27.9", "27.10",
+        ];
+        for pair in ordered.windows(2) {
+            let lower = pair[0].parse::<ProtocVersion>().unwrap();
+            let higher = pair[1].parse::<ProtocVersion>().unwrap();
+            assert!(lower < higher, "expected {lower} < {higher}");
+        }
+
+        let without_patch = ProtocVersion::new(3, 20);
+        let with_patch = "3.20.0".parse::<ProtocVersion>().unwrap();
+        assert_ne!(without_patch, with_patch);
+        assert!(without_patch < with_patch);
+    }
+
+    #[test]
+    fn test_rust_expression() {
+        assert_eq!(
+            "ProtocVersion::new(27, 0)",
+            ProtocVersion::new(27, 0).rust_expression()
+        );
+        assert_eq!(
+            "ProtocVersion { patch: Some(3), prerelease: None, ..ProtocVersion::new(3, 20) }",
+            "3.20.3".parse::<ProtocVersion>().unwrap().rust_expression()
+        );
+    }
+
+    #[test]
+    fn test_version_req_matches() {
+        let tests = [
+            (">=31", "31.0", true),
+            (">=31", "30.2", false),
+            (">=31", "34.1", true),
+            (">31", "31.1", false),
+            (">31", "32.0", true),
+            (">31.0", "31.1", true),
+            (">3.20.2", "3.20.3", true),
+            (">3.20.3", "3.20.3", false),
+            (">=3.20", "21.12", true),
+            ("<34", "33.5", true),
+            ("<34", "34.0", false),
+            ("<=33", "33.5", true),
+            ("<=33", "34.0", false),
+            ("<=33.2", "33.2", true),
+            ("<=3.20.3", "3.20.3", true),
+            ("<3.20.3", "3.20.3", false),
+            ("<22", "3.19.6", true),
+            ("~33", "33.0", true),
+            ("~33", "33.5", true),
+            ("~33", "34.0", false),
+            ("~33.2", "33.2", true),
+            ("~33.2", "33.5", false),
+            ("^33.2", "33.5", true),
+            ("^33.2", "33.1", false),
+            ("^33.2", "34.0", false),
+            ("=33", "33.5", true),
+            ("=33", "34.0", false),
+            ("33.5", "33.5", true),
+            ("33.5", "33.2", false),
+            ("3.20.3", "3.20.3", true),
+            ("3.20", "3.20.3", true),
+            ("=3", "3.20.3", true),
+            ("=3", "21.12", false),
+            // 3.21.x is the same as 21.x
+            ("3.21.12", "21.12", true),
+            ("~3.21", "21.12", true),
+            ("~3.21", "22.0", false),
+            (">=30, <34", "33.5", true),
+            (">=30, <34", "29.3", false),
+            (">=30, <34", "34.0", false),
+            ("*", "27.0", true),
+            // prereleases only match when requested
+            (">=33", "34.0-rc1", false),
+            ("*", "34.0-rc1", false),
+            (">=34.0-rc1", "34.0-rc2", true),
+            (">=34.0-rc1", "34.0", true),
+            ("=34.0-rc1", "34.0-rc1", true),
+            ("=34.0-rc1", "34.0-rc2", false),
+            ("<34", "34.0-rc1", false),
+        ];
+        for (req, version, expected) in tests {
+            let req = req.parse::<VersionReq>().unwrap();
+            let version = version.parse::<ProtocVersion>().unwrap();
+            assert_eq!(
+                expected,
+                req.matches(version),
+                "req={req} version={version}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_version_req_matches_prerelease() {
+        let rc = "34.0-rc1".parse::<ProtocVersion>().unwrap();
+        for (req, expected) in [
+            (">=33", true),
+            ("*", true),
+            ("~34", false),
+            ("<34", false),
+            ("<=33", false),
+        ] {
+            let req = req.parse::<VersionReq>().unwrap();
+            assert_eq!(expected, req.matches_prerelease(rc), "req={req}");
+        }
+    }
+
+    #[test]
+    fn test_file_version() {
+        assert_eq!("34.0", ProtocVersion::new(34, 0).file_version());
+        assert_eq!(
+            "34.0-rc-1",
+            "34.0-rc1".parse::<ProtocVersion>().unwrap().file_version()
+        );
+        assert_eq!(
+            "3.20.0-rc-2",
+            "v3.20.0-rc2"
+                .parse::<ProtocVersion>()
+                .unwrap()
+                .file_version()
+        );
+    }
+
+    #[test]
+    fn test_version_req_parse_display() {
+        for (input, expected) in [
+            (">=31", ">=31"),
+            (">=30,<34", ">=30, <34"),
+            ("  ~ 33 ", "~33"),
+            ("33.1", "=33.1"),
+            ("*", "*"),
+            ("^3.20.3", "^3.20.3"),
+            ("^3.21.12", "^21.12"),
+            ("=34.0-rc1", "=34.0-rc1"),
+        ] {
+            let req = input.parse::<VersionReq>().unwrap();
+            assert_eq!(expected, req.to_string());
+            assert_eq!(req, req.to_string().parse().unwrap());
+        }
+
+        for invalid in ["", ">=", "~x", ">=31,", "31-rc1", "=>31", "1.2.3.4"] {
+            let err = invalid.parse::<VersionReq>().unwrap_err();
+            assert!(
+                err.to_string()
+                    .starts_with("invalid protoc version requirement: "),
+                "unexpected error for {invalid:?}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_version_req_exact_version() {
+        let version = ProtocVersion::new(33, 5);
+        assert_eq!(Some(version), VersionReq::exact(version).exact_version());
+        assert_eq!(
+            Some(version),
+            "33.5".parse::<VersionReq>().unwrap().exact_version()
+        );
+        assert_eq!(None, "=33".parse::<VersionReq>().unwrap().exact_version());
+        assert_eq!(
+            None,
+            ">=33.5".parse::<VersionReq>().unwrap().exact_version()
+        );
+        assert_eq!(None, VersionReq::any().exact_version());
+    }
+}
@@ -0,0 +1,84 @@
+//! Finds the repository's protoc version file, similar to `rust-toolchain.toml`.
+
+use std::path::{Path, PathBuf};
+
+use crate::{error::Error, protoc_version::VersionReq};
+
+/// Names of the version file, in order of preference.
+const VERSION_FILE_NAMES: &[&str] = &["protoc-version", ".protoc-version"];
+
+/// The version requirement from a version file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VersionFile {
+    pub path: PathBuf,
+    pub version: VersionReq,
+}
+
+/// Returns the version file in `start_dir` or the closest parent directory, or None if there is
+/// no version file.
+pub fn find(start_dir: &Path) -> Result<Option<VersionFile>, Error> {
+    for dir in start_dir.ancestors() {
+        for name in VERSION_FILE_NAMES {
+            let path = dir.join(name);
+            if path.is_file() {
+                let version = read(&path).map_err(|e| e.prefixed(path.display().to_string()))?;
+                return Ok(Some(VersionFile { path, version }));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Reads the version from the first line that is not empty or a # comment.
+fn read(path: &Path) -> Result<VersionReq, Error> {
+    let contents = std::fs::read_to_string(path)?;
+    let line = contents
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .ok_or_else(|| Error::from_string(String::from("does not contain a version")))?;
+    line.parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protoc_version::ProtocVersion;
+
+    #[test]
+    fn test_find() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let nested_dir = tempdir.path().join("crates").join("example");
+        std::fs::create_dir_all(&nested_dir).unwrap();
+        assert_eq!(None, find(&nested_dir).unwrap());
+
+        let hidden_path = tempdir.path().join(".protoc-version");
+        std::fs::write(&hidden_path, "# pinned for the whole repo\n\n33.5\n").unwrap();
+        assert_eq!(
+            Some(VersionFile {
+                path: hidden_path,
+                version: ProtocVersion::new(33, 5).into(),
+            }),
+            find(&nested_dir).unwrap()
+        );
+
+        // the closest file wins
+        let nested_path = tempdir.path().join("crates").join("protoc-version");
+        std::fs::write(&nested_path, ">=31\n").unwrap();
+        assert_eq!(
+            Some(VersionFile {
+                path: nested_path.clone(),
+                version: ">=31".parse().unwrap(),
+            }),
+            find(&nested_dir).unwrap()
+        );
+
+        std::fs::write(&nested_path, "# no version\n").unwrap();
+        let err = find(&nested_dir).unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("protoc-version: does not contain a version"),
+            "unexpected error: {err}"
+        );
+    }
+}
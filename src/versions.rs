@@ -1,6 +1,12 @@
-use crate::error::Error;
+use crate::{
+    error::{Error, ErrorKind},
+    protoc_version::{ProtocVersion, VersionReq},
+};
 use hex_literal::hex;
-use std::fmt::Display;
+use std::{
+    fmt::{Display, Write},
+    str::FromStr,
+};
 
 /// Operating system used to run protoc. The Display trait returns the string used for protoc URLs.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -20,14 +26,18 @@ impl OS {
     /// Returns the operating system executing this function.
     ///
     /// # Panics
-    /// If this is run on an unsupported operating system.
+    /// If this is run on an unsupported operating system. See [`OS::detect`].
     #[must_use]
     pub fn current() -> Self {
-        match std::env::consts::OS {
-            "linux" => Self::Linux,
-            "macos" => Self::OSX,
-            unsupported_os => panic!("unsupported OS: {unsupported_os}"),
-        }
+        Self::detect().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Returns the operating system executing this function.
+    ///
+    /// # Errors
+    /// If this is run on an unsupported operating system.
+    pub fn detect() -> Result<Self, Error> {
+        std::env::consts::OS.parse()
     }
 
     /// Returns all defined enum values.
@@ -44,6 +54,16 @@ impl OS {
             Self::OSX => "OSX",
         }
     }
+
+    /// Returns true if protoc releases include a binary for this OS and CPU architecture.
+    #[must_use]
+    pub const fn has_release(self, cpu: CPUArch) -> bool {
+        match cpu {
+            CPUArch::AArch64 | CPUArch::X86_64 => true,
+            CPUArch::X86_32 => matches!(self, Self::Linux),
+            CPUArch::UniversalBinary => matches!(self, Self::OSX),
+        }
+    }
 }
 
 impl Display for OS {
@@ -56,6 +76,23 @@ impl Display for OS {
     }
 }
 
+/// Parses the protoc URL label (e.g. `osx`), the [`std::env::consts::OS`] name (e.g. `macos`), or
+/// the Rust enum identifier (e.g. `OSX`). Matching is case-insensitive.
+impl FromStr for OS {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "linux" => Ok(Self::Linux),
+            "osx" | "macos" | "darwin" => Ok(Self::OSX),
+            _ => Err(Error::new(
+                ErrorKind::UnsupportedPlatform,
+                format!("unsupported OS: {s}"),
+            )),
+        }
+    }
+}
+
 /// CPU architecture used to run protoc. The Display trait returns the string used for protoc URLs.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum CPUArch {
@@ -65,34 +102,59 @@ pub enum CPUArch {
     /// Intel/AMD x86-64: "x86_64" in protoc URLs.
     #[allow(clippy::doc_markdown)]
     X86_64,
+    /// Intel/AMD 32-bit x86: "x86_32" in protoc URLs. Only published for [`OS::Linux`].
+    #[allow(clippy::doc_markdown)]
+    X86_32,
+    /// Mac OS X universal binary that runs on both AArch64 and X86_64: "universal_binary" in
+    /// protoc URLs. Only published for [`OS::OSX`].
+    #[allow(clippy::doc_markdown)]
+    UniversalBinary,
 }
 
 impl CPUArch {
     /// Returns the CPU architecture executing this function.
     ///
     /// # Panics
-    /// If run on an unsupported architecture.
+    /// If run on an unsupported architecture. See [`CPUArch::detect`].
     #[must_use]
     pub fn current() -> Self {
-        match std::env::consts::ARCH {
-            "aarch64" => Self::AArch64,
-            "x86_64" => Self::X86_64,
-            unsupported_arch => panic!("unsupported arch: {unsupported_arch}"),
-        }
+        Self::detect().unwrap_or_else(|e| panic!("{e}"))
     }
 
-    /// Returns all defined enum values.
+    /// Returns the CPU architecture executing this function.
+    ///
+    /// # Errors
+    /// If run on an unsupported architecture.
+    pub fn detect() -> Result<Self, Error> {
+        std::env::consts::ARCH.parse()
+    }
+
+    /// Returns the CPU architectures with protoc releases for every OS. [`CPUArch::X86_32`] and
+    /// [`CPUArch::UniversalBinary`] are only released for one OS, so they are not included; use
+    /// [`Platform::all`] to list every platform with releases.
     #[must_use]
     pub const fn all() -> &'static [Self] {
         &[Self::AArch64, Self::X86_64]
     }
 
+    /// Returns all defined enum values, including those only released for one OS.
+    const fn all_with_releases() -> &'static [Self] {
+        &[
+            Self::AArch64,
+            Self::X86_64,
+            Self::X86_32,
+            Self::UniversalBinary,
+        ]
+    }
+
     /// Returns the Rust enum identifier as used in code.
     #[must_use]
     pub const fn code_label(self) -> &'static str {
         match self {
             Self::AArch64 => "AArch64",
             Self::X86_64 => "X86_64",
+            Self::X86_32 => "X86_32",
+            Self::UniversalBinary => "UniversalBinary",
         }
     }
 }
@@ -102,455 +164,1043 @@ impl Display for CPUArch {
         let s = match self {
             Self::AArch64 => "aarch_64",
             Self::X86_64 => "x86_64",
+            Self::X86_32 => "x86_32",
+            Self::UniversalBinary => "universal_binary",
         };
         write!(f, "{s}")
     }
 }
 
+/// Parses the protoc URL label (e.g. `aarch_64`), the [`std::env::consts::ARCH`] name (e.g.
+/// `aarch64`), or the Rust enum identifier (e.g. `AArch64`). Matching is case-insensitive.
+impl FromStr for CPUArch {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "aarch_64" | "aarch64" | "arm64" => Ok(Self::AArch64),
+            "x86_64" => Ok(Self::X86_64),
+            "x86_32" | "x86" => Ok(Self::X86_32),
+            "universal_binary" | "universalbinary" => Ok(Self::UniversalBinary),
+            _ => Err(Error::new(
+                ErrorKind::UnsupportedPlatform,
+                format!("unsupported arch: {s}"),
+            )),
+        }
+    }
+}
+
+/// An operating system and CPU architecture pair that identifies a protoc binary. The Display
+/// trait returns the string used for protoc URLs (e.g. "linux-x86_64"), which is also accepted by
+/// [`FromStr`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Platform {
+    pub os: OS,
+    pub cpu: CPUArch,
+}
+
+impl Platform {
+    #[must_use]
+    pub const fn new(os: OS, cpu: CPUArch) -> Self {
+        Self { os, cpu }
+    }
+
+    /// Returns the platform executing this function.
+    ///
+    /// # Errors
+    /// If this is run on an unsupported operating system or CPU architecture.
+    pub fn detect() -> Result<Self, Error> {
+        Ok(Self::new(OS::detect()?, CPUArch::detect()?))
+    }
+
+    /// Returns the platform for a Rust target triple, such as the `TARGET` or `HOST` environment
+    /// variables that Cargo sets for build scripts (e.g. "aarch64-apple-darwin").
+    ///
+    /// # Errors
+    /// If the target triple is not for a supported operating system and CPU architecture.
+    pub fn from_target_triple(triple: &str) -> Result<Self, Error> {
+        let mut parts = triple.split('-');
+        let cpu = parts.next().unwrap_or_default();
+        let cpu = match cpu {
+            // Rust uses "aarch64" and "arm64", but they are the same architecture
+            "aarch64" | "arm64" => CPUArch::AArch64,
+            "x86_64" => CPUArch::X86_64,
+            "i586" | "i686" => CPUArch::X86_32,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::UnsupportedPlatform,
+                    format!("unsupported arch in target triple: {triple}"),
+                ));
+            }
+        };
+
+        // the remaining parts are vendor-os-env, where vendor and env are optional
+        let rest = parts.collect::<Vec<_>>();
+        let os = if rest.contains(&"darwin") {
+            OS::OSX
+        } else if rest.contains(&"linux") && !rest.iter().any(|part| part.starts_with("android")) {
+            OS::Linux
+        } else {
+            return Err(Error::new(
+                ErrorKind::UnsupportedPlatform,
+                format!("unsupported OS in target triple: {triple}"),
+            ));
+        };
+        Ok(Self::new(os, cpu))
+    }
+
+    /// Returns all platforms with published protoc releases.
+    #[must_use]
+    pub fn all() -> Vec<Self> {
+        let mut platforms = Vec::new();
+        for os in OS::all() {
+            for cpu in CPUArch::all_with_releases() {
+                if os.has_release(*cpu) {
+                    platforms.push(Self::new(*os, *cpu));
+                }
+            }
+        }
+        platforms
+    }
+}
+
+impl Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.os, self.cpu)
+    }
+}
+
+/// Parses either os-cpu (e.g. `linux-x86_64`) or a Rust target triple (e.g.
+/// `aarch64-apple-darwin`).
+impl FromStr for Platform {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((os, cpu)) = s.split_once('-')
+            && let (Ok(os), Ok(cpu)) = (os.parse(), cpu.parse())
+        {
+            return Ok(Self::new(os, cpu));
+        }
+        Self::from_target_triple(s).map_err(|e| {
+            Error::new(
+                e.kind(),
+                format!(
+                    "invalid platform: {s}: must be os-cpu or a target triple; known platforms: {}",
+                    Self::all()
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            )
+        })
+    }
+}
+
+/// Controls when the Mac OS X universal binary is used instead of the CPU-specific binary.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum UniversalBinary {
+    /// Only use the CPU-specific binary.
+    Never,
+    /// Use the universal binary if there is no known CPU-specific binary for the version.
+    #[default]
+    Fallback,
+    /// Use the universal binary if it is known, otherwise use the CPU-specific binary.
+    Prefer,
+}
+
+/// Parses the lower-case variant name (e.g. `prefer`).
+impl FromStr for UniversalBinary {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(Self::Never),
+            "fallback" => Ok(Self::Fallback),
+            "prefer" => Ok(Self::Prefer),
+            _ => Err(Error::from_string(format!(
+                "invalid universal binary setting: {s:?}: must be never, fallback or prefer"
+            ))),
+        }
+    }
+}
+
 type Sha256HashResult = [u8; 32];
 
 /// Defines an expected hash for a specific protoc binary release.
-struct KnownVersion {
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct KnownVersion {
     os: OS,
     cpu: CPUArch,
-    version: &'static str,
+    version: ProtocVersion,
     hash: Sha256HashResult,
 }
 
-/// The most recent version of protoc that we know about.
-pub const LATEST_VERSION: &str = KNOWN_VERSIONS[KNOWN_VERSIONS.len() - 1].version;
+impl KnownVersion {
+    /// Returns the expected SHA-256 `hash` of the release Zip file for `version` and `platform`.
+    #[must_use]
+    pub const fn new(version: ProtocVersion, platform: Platform, hash: [u8; 32]) -> Self {
+        Self {
+            os: platform.os,
+            cpu: platform.cpu,
+            version,
+            hash,
+        }
+    }
+
+    #[must_use]
+    pub const fn version(&self) -> ProtocVersion {
+        self.version
+    }
+
+    #[must_use]
+    pub const fn platform(&self) -> Platform {
+        Platform::new(self.os, self.cpu)
+    }
+
+    /// Returns the SHA-256 hash of the release Zip file.
+    #[must_use]
+    pub const fn hash(&self) -> [u8; 32] {
+        self.hash
+    }
+}
+
+/// The date a protoc release was published.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ReleaseDate {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl ReleaseDate {
+    #[must_use]
+    pub const fn new(year: u16, month: u8, day: u8) -> Self {
+        Self { year, month, day }
+    }
+}
+
+impl Display for ReleaseDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// How serious an [`Advisory`] is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The release has a known problem, but may be used. Builds print a warning.
+    Warning,
+    /// The release must not be used, e.g. because of a code generation bug or a security issue.
+    /// Builds fail unless known bad releases are allowed, and version requirements skip it.
+    KnownBad,
+}
+
+/// A known problem with a protoc release.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Advisory {
+    pub severity: Severity,
+    /// Describes the problem, e.g. a CVE or a link to the bug.
+    pub reason: &'static str,
+}
+
+/// Metadata about a protoc release, which applies to all of its platforms.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ReleaseInfo {
+    version: ProtocVersion,
+    published: Option<ReleaseDate>,
+    advisory: Option<Advisory>,
+}
+
+impl ReleaseInfo {
+    #[must_use]
+    pub const fn new(
+        version: ProtocVersion,
+        published: Option<ReleaseDate>,
+        advisory: Option<Advisory>,
+    ) -> Self {
+        Self {
+            version,
+            published,
+            advisory,
+        }
+    }
+
+    #[must_use]
+    pub const fn version(&self) -> ProtocVersion {
+        self.version
+    }
+
+    /// Returns the date the release was published, or None if it is not known.
+    #[must_use]
+    pub const fn published(&self) -> Option<ReleaseDate> {
+        self.published
+    }
+
+    /// Returns the known problem with this release, or None if there is none.
+    #[must_use]
+    pub const fn advisory(&self) -> Option<Advisory> {
+        self.advisory
+    }
+
+    /// Returns true if this release must not be used.
+    #[must_use]
+    pub const fn is_known_bad(&self) -> bool {
+        matches!(
+            self.advisory,
+            Some(Advisory {
+                severity: Severity::KnownBad,
+                ..
+            })
+        )
+    }
+}
+
+/// Returns the metadata for `version`, or None if there is none.
+#[must_use]
+pub fn release_info(version: ProtocVersion) -> Option<&'static ReleaseInfo> {
+    RELEASE_INFO.iter().find(|info| info.version == version)
+}
+
+/// Returns the known bad versions, which version requirements skip.
+pub fn known_bad_versions() -> Vec<ProtocVersion> {
+    RELEASE_INFO
+        .iter()
+        .filter(|info| info.is_known_bad())
+        .map(ReleaseInfo::version)
+        .collect()
+}
+
+/// Parses a SHA-256 hash written as 64 hex digits.
+pub fn parse_hash(s: &str) -> Result<Sha256HashResult, Error> {
+    let invalid = || Error::from_string(format!("invalid sha256: {s:?}"));
+    if s.len() != 64 {
+        return Err(invalid());
+    }
+    let mut hash = [0; 32];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        let digits = s.get(i * 2..i * 2 + 2).ok_or_else(invalid)?;
+        *byte = u8::from_str_radix(digits, 16).map_err(|_| invalid())?;
+    }
+    Ok(hash)
+}
+
+/// Returns a SHA-256 hash as 64 lower-case hex digits, the format read by [`parse_hash`].
+pub fn format_hash(hash: &Sha256HashResult) -> String {
+    let mut s = String::with_capacity(hash.len() * 2);
+    for byte in hash {
+        write!(s, "{byte:02x}").unwrap();
+    }
+    s
+}
+
+/// Returns the known versions with `trusted` added. Trusted hashes override known hashes for the
+/// same version and platform.
+pub fn with_trusted_hashes(trusted: &[KnownVersion]) -> Vec<KnownVersion> {
+    let mut known_versions = trusted.to_vec();
+    known_versions.extend(KNOWN_VERSIONS.iter().filter(|known| {
+        !trusted.iter().any(|trusted| {
+            trusted.version == known.version && trusted.platform() == known.platform()
+        })
+    }));
+    known_versions
+}
+
+/// Returns all protoc releases with known hashes, in increasing version order.
+pub fn known_releases() -> impl Iterator<Item = &'static KnownVersion> {
+    KNOWN_VERSIONS.iter()
+}
+
+/// Returns the versions with a known release for `platform`, in increasing order. This does not
+/// include versions that only have releases for compatible platforms.
+pub fn versions_for(platform: Platform) -> impl Iterator<Item = ProtocVersion> {
+    known_releases()
+        .filter(move |known| known.platform() == platform)
+        .map(KnownVersion::version)
+}
+
+/// Returns the newest version with a known release for `platform`, or None if there are no known
+/// releases for it. This does not include prereleases.
+#[must_use]
+pub fn latest_for(platform: Platform) -> Option<ProtocVersion> {
+    versions_for(platform)
+        .filter(|version| !version.is_prerelease())
+        .max()
+}
+
+/// The most recent version of protoc that we know about. This is never a prerelease.
+pub const LATEST_VERSION: ProtocVersion = latest_release_version(KNOWN_VERSIONS);
 
-pub fn known_hash(os: OS, cpu: CPUArch, version: &str) -> Result<Sha256HashResult, Error> {
-    for known in KNOWN_VERSIONS {
-        if known.os == os && known.cpu == cpu && known.version == version {
-            return Ok(known.hash);
+/// Returns the last version that is not a prerelease.
+const fn latest_release_version(known_versions: &[KnownVersion]) -> ProtocVersion {
+    let mut index = known_versions.len();
+    while index > 0 {
+        index -= 1;
+        if !known_versions[index].version.is_prerelease() {
+            return known_versions[index].version;
         }
     }
-    Err(Error::from_string(format!(
-        "unknown hash for {os} {cpu} {version}"
-    )))
+    panic!("KNOWN_VERSIONS must contain a release");
+}
+
+fn find_hash(
+    known_versions: &[KnownVersion],
+    platform: Platform,
+    version: ProtocVersion,
+) -> Option<Sha256HashResult> {
+    known_versions
+        .iter()
+        .find(|known| {
+            known.os == platform.os && known.cpu == platform.cpu && known.version == version
+        })
+        .map(|known| known.hash)
 }
 
+/// Platforms that can run the releases of another platform, in order of preference. These are
+/// used when there is no known release for the exact platform. `linux-x86_64` can also run
+/// `linux-x86_32` releases; add that entry once [`KNOWN_VERSIONS`] has hashes for them.
+const COMPATIBLE_RELEASES: &[(Platform, Platform)] = &[
+    (
+        Platform::new(OS::OSX, CPUArch::AArch64),
+        Platform::new(OS::OSX, CPUArch::UniversalBinary),
+    ),
+    (
+        Platform::new(OS::OSX, CPUArch::X86_64),
+        Platform::new(OS::OSX, CPUArch::UniversalBinary),
+    ),
+];
+
+/// Returns the platforms with releases that can run on `platform`, in order of preference.
+pub fn candidate_releases(platform: Platform, universal_binary: UniversalBinary) -> Vec<Platform> {
+    let mut candidates = vec![platform];
+    candidates.extend(
+        COMPATIBLE_RELEASES
+            .iter()
+            .filter(|(runs_on, _)| *runs_on == platform)
+            .map(|(_, release)| *release),
+    );
+
+    match universal_binary {
+        UniversalBinary::Never => {
+            candidates.retain(|candidate| candidate.cpu != CPUArch::UniversalBinary);
+        }
+        UniversalBinary::Fallback => {}
+        UniversalBinary::Prefer => {
+            if let Some(index) = candidates
+                .iter()
+                .position(|candidate| candidate.cpu == CPUArch::UniversalBinary)
+            {
+                let universal = candidates.remove(index);
+                candidates.insert(0, universal);
+            }
+        }
+    }
+    candidates
+}
+
+/// A protoc release selected for download.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Release {
+    pub version: ProtocVersion,
+    /// The platform of the release, which may be compatible with the requested platform.
+    pub platform: Platform,
+    pub hash: Sha256HashResult,
+}
+
+/// Returns the release to download for `version` from `known_versions`. If there is no known
+/// release for `platform`, this returns a compatible release from [`COMPATIBLE_RELEASES`]. On Mac
+/// OS X, `universal_binary` controls when the universal binary is used.
+pub fn select_release(
+    known_versions: &[KnownVersion],
+    platform: Platform,
+    version: ProtocVersion,
+    universal_binary: UniversalBinary,
+) -> Result<Release, Error> {
+    let candidates = candidate_releases(platform, universal_binary);
+    for &candidate in &candidates {
+        if let Some(hash) = find_hash(known_versions, candidate, version) {
+            return Ok(Release {
+                version,
+                platform: candidate,
+                hash,
+            });
+        }
+    }
+    let Platform { os, cpu } = platform;
+    let hint = unknown_release_hint(known_versions, platform, &candidates, Some(version));
+    Err(Error::new(
+        ErrorKind::UnknownVersion,
+        format!("unknown hash for {os} {cpu} {version}: {hint}"),
+    ))
+}
+
+/// Returns hints for an error about `platform`, which can use releases for `candidates`: the
+/// latest version with a release, and if `version` is set, the other platforms that have it or
+/// the closest versions with a release.
+fn unknown_release_hint(
+    known_versions: &[KnownVersion],
+    platform: Platform,
+    candidates: &[Platform],
+    version: Option<ProtocVersion>,
+) -> String {
+    let mut platform_versions = known_versions
+        .iter()
+        .filter(|known| candidates.contains(&known.platform()))
+        .map(|known| known.version)
+        .collect::<Vec<_>>();
+    platform_versions.sort_unstable();
+    platform_versions.dedup();
+    let Some(latest) = latest_release(&platform_versions) else {
+        return format!("no protoc versions are known for {platform}");
+    };
+
+    let Some(version) = version else {
+        return format!("the latest known version for {platform} is {latest}");
+    };
+    let mut other_platforms = known_versions
+        .iter()
+        .filter(|known| known.version == version)
+        .map(|known| known.platform().to_string())
+        .collect::<Vec<_>>();
+    other_platforms.sort_unstable();
+    other_platforms.dedup();
+    let mut hints = Vec::new();
+    if !other_platforms.is_empty() {
+        hints.push(format!(
+            "protoc {version} is only known for {}",
+            other_platforms.join(", ")
+        ));
+    }
+    hints.push(format!(
+        "the latest known version for {platform} is {latest}"
+    ));
+    if other_platforms.is_empty() && version < latest {
+        // versions are sorted, so the closest are on either side of where version would be
+        let index = platform_versions.partition_point(|known| *known < version);
+        let closest = [index.checked_sub(1), Some(index)]
+            .into_iter()
+            .flatten()
+            .filter_map(|i| platform_versions.get(i))
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        hints.push(format!("did you mean {}?", closest.join(" or ")));
+    }
+    hints.join("; ")
+}
+
+/// Returns the newest version that is not a prerelease, or the newest prerelease if there are
+/// only prereleases. `versions` must be sorted.
+fn latest_release(versions: &[ProtocVersion]) -> Option<ProtocVersion> {
+    versions
+        .iter()
+        .rev()
+        .find(|version| !version.is_prerelease())
+        .or_else(|| versions.last())
+        .copied()
+}
+
+/// Returns the number of minor releases in `known_versions` that are newer than `version`. Patch
+/// releases and prereleases are not counted. For example, 33.5 is 2 minor releases behind 34.1.
+pub fn minor_releases_behind(
+    version: ProtocVersion,
+    known_versions: impl IntoIterator<Item = ProtocVersion>,
+) -> usize {
+    let mut newer = known_versions
+        .into_iter()
+        .filter(|known| !known.is_prerelease())
+        .map(|known| (known.major, known.minor))
+        .filter(|&minor_release| minor_release > (version.major, version.minor))
+        .collect::<Vec<_>>();
+    newer.sort_unstable();
+    newer.dedup();
+    newer.len()
+}
+
+/// Returns hints for a requirement that matches no version in `known_versions`, the same as the
+/// errors from [`select_release`] and [`resolve_release`] for `platform`.
+pub fn unknown_requirement_hint(
+    known_versions: &[KnownVersion],
+    platform: Platform,
+    universal_binary: UniversalBinary,
+    requirement: &VersionReq,
+) -> String {
+    let candidates = candidate_releases(platform, universal_binary);
+    unknown_release_hint(
+        known_versions,
+        platform,
+        &candidates,
+        requirement.exact_version(),
+    )
+}
+
+/// Returns the release of the newest version in `known_versions` that matches `requirement` and
+/// has a release for `platform`. Prereleases are only considered if `prereleases` is true, or if
+/// the requirement names a prerelease. Versions in `excluded` are skipped, unless the requirement
+/// is an exact version. See [`select_release`].
+pub fn resolve_release(
+    known_versions: &[KnownVersion],
+    platform: Platform,
+    requirement: &VersionReq,
+    universal_binary: UniversalBinary,
+    prereleases: bool,
+    excluded: &[ProtocVersion],
+) -> Result<Release, Error> {
+    if let Some(version) = requirement.exact_version() {
+        return select_release(known_versions, platform, version, universal_binary);
+    }
+
+    let mut matching_versions = known_versions
+        .iter()
+        .map(|known| known.version)
+        .filter(|version| !excluded.contains(version))
+        .filter(|version| {
+            if prereleases {
+                requirement.matches_prerelease(*version)
+            } else {
+                requirement.matches(*version)
+            }
+        })
+        .collect::<Vec<_>>();
+    matching_versions.sort_unstable();
+    matching_versions.dedup();
+    for version in matching_versions.into_iter().rev() {
+        if let Ok(release) = select_release(known_versions, platform, version, universal_binary) {
+            return Ok(release);
+        }
+    }
+    let candidates = candidate_releases(platform, universal_binary);
+    let hint = unknown_release_hint(known_versions, platform, &candidates, None);
+    Err(Error::new(
+        ErrorKind::UnknownVersion,
+        format!("no known protoc version matches {requirement} for {platform}: {hint}"),
+    ))
+}
+
+/// Metadata about protoc releases, in increasing version number order. Versions without metadata
+/// are not listed. `protochashes` prints an entry with the publish date for new releases; add an
+/// [`Advisory`] when a release is found to have a problem.
+const RELEASE_INFO: &[ReleaseInfo] = &[];
+
 /// All binary releases of protoc we know about. This is in increasing version number order.
 const KNOWN_VERSIONS: &[KnownVersion] = &[
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::X86_64,
-        version: "27.0",
+        version: ProtocVersion::new(27, 0),
         hash: hex!("e2bdce49564dbad4676023d174d9cdcf932238bc0b56a8349a5cb27bbafc26b0"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::AArch64,
-        version: "27.0",
+        version: ProtocVersion::new(27, 0),
         hash: hex!("1e4b2d8b145afe99a36602f305165761e46d2525aa94cbb907e2e983be6717ac"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::AArch64,
-        version: "27.1",
+        version: ProtocVersion::new(27, 1),
         hash: hex!("8809c2ec85368c6b6e9af161b6771a153aa92670a24adbe46dd34fa02a04df2f"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::X86_64,
-        version: "27.1",
+        version: ProtocVersion::new(27, 1),
         hash: hex!("8970e3d8bbd67d53768fe8c2e3971bdd71e51cfe2001ca06dacad17258a7dae3"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::AArch64,
-        version: "27.1",
+        version: ProtocVersion::new(27, 1),
         hash: hex!("03b7af1bf469e7285dc51976ee5fa99412704dbd1c017105114852a37b165c12"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::X86_64,
-        version: "27.1",
+        version: ProtocVersion::new(27, 1),
         hash: hex!("8520d944f3a3890fa296a3b3b0d4bb18337337e2526bbbf1b507eeea3c2a1ec4"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::AArch64,
-        version: "27.2",
+        version: ProtocVersion::new(27, 2),
         hash: hex!("ff4760bd4ae510d533e528cc6deb8e32e53f383f0ec01b0327233b4c2e8db314"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::X86_64,
-        version: "27.2",
+        version: ProtocVersion::new(27, 2),
         hash: hex!("4a95e0ea2e51720af86a92f48d4997c8756923a9d0c58fd8a850657cd7479caf"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::AArch64,
-        version: "27.2",
+        version: ProtocVersion::new(27, 2),
         hash: hex!("877de17b5d2662b96e68a6e208cb1851437ab3e2b419c2ef5b7b873ffac5357d"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::X86_64,
-        version: "27.2",
+        version: ProtocVersion::new(27, 2),
         hash: hex!("abc25a236571612d45eb4b6b6e6abe3ac9aecc34b195f76f248786844f5619c7"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::AArch64,
-        version: "27.3",
+        version: ProtocVersion::new(27, 3),
         hash: hex!("bdad36f3ad7472281d90568c4956ea2e203c216e0de005c6bd486f1920f2751c"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::X86_64,
-        version: "27.3",
+        version: ProtocVersion::new(27, 3),
         hash: hex!("6dab2adab83f915126cab53540d48957c40e9e9023969c3e84d44bfb936c7741"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::AArch64,
-        version: "27.3",
+        version: ProtocVersion::new(27, 3),
         hash: hex!("b22116bd97cdbd7ea25346abe635a9df268515fe5ef5afa93cd9a68fc2513f84"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::X86_64,
-        version: "27.3",
+        version: ProtocVersion::new(27, 3),
         hash: hex!("ce282648fed0e7fbd6237d606dc9ec168dd2c1863889b04efa0b19c47da65d1b"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::AArch64,
-        version: "28.2",
+        version: ProtocVersion::new(28, 2),
         hash: hex!("91d8253cdc0f0f0fc51c2b69c80677996632f525ad84504bfa5b4ee38ad3e49c"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::X86_64,
-        version: "28.2",
+        version: ProtocVersion::new(28, 2),
         hash: hex!("2febfd42b59ce93a28eb789019a470a3dd0449619bc04f84dad1333da261dec1"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::AArch64,
-        version: "28.2",
+        version: ProtocVersion::new(28, 2),
         hash: hex!("7bb048f52841789d9ec61983be0ce4c9e4fb3bd9a143462820ba9a3be0a03797"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::X86_64,
-        version: "28.2",
+        version: ProtocVersion::new(28, 2),
         hash: hex!("232f07d12bf4806207a79ec2c7378301c52e6f2f7efdd21c0dd416f0bda103ec"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::AArch64,
-        version: "29.0",
+        version: ProtocVersion::new(29, 0),
         hash: hex!("305f1be5ae7b2f39451870b312b45c1e0ba269901c83ba16d85f9f9d1441b348"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::X86_64,
-        version: "29.0",
+        version: ProtocVersion::new(29, 0),
         hash: hex!("3c51065af3b9a606d9e18a1bf628143734ff4b9e69725d6459857430ba7a78df"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::AArch64,
-        version: "29.0",
+        version: ProtocVersion::new(29, 0),
         hash: hex!("b2b59f03b030c8a748623d682a8b5bc9cc099e4bcfd06b8964ce89ec065b3103"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::X86_64,
-        version: "29.0",
+        version: ProtocVersion::new(29, 0),
         hash: hex!("e7a1cffc82e21daa67833011449c70ddff1eba3b115934387e6e8141efab092f"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::AArch64,
-        version: "29.2",
+        version: ProtocVersion::new(29, 2),
         hash: hex!("29cf483e2fb21827e5fac4964e35eae472a238e28c762f02fb17dcd93ff8b89f"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::X86_64,
-        version: "29.2",
+        version: ProtocVersion::new(29, 2),
         hash: hex!("52e9e7ece55c7e30e7e8bbd254b4b21b408a5309bca826763c7124b696a132e9"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::AArch64,
-        version: "29.2",
+        version: ProtocVersion::new(29, 2),
         hash: hex!("0e153a38d6da19594c980e7f7cd3ea0ddd52c9da1068c03c0d8533369fbfeb20"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::X86_64,
-        version: "29.2",
+        version: ProtocVersion::new(29, 2),
         hash: hex!("ba2bd983b5f06ec38d663b602884a597dea3990a43803d7e153ed8f7c54269e1"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::AArch64,
-        version: "29.3",
+        version: ProtocVersion::new(29, 3),
         hash: hex!("6427349140e01f06e049e707a58709a4f221ae73ab9a0425bc4a00c8d0e1ab32"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::X86_64,
-        version: "29.3",
+        version: ProtocVersion::new(29, 3),
         hash: hex!("3e866620c5be27664f3d2fa2d656b5f3e09b5152b42f1bedbf427b333e90021a"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::AArch64,
-        version: "29.3",
+        version: ProtocVersion::new(29, 3),
         hash: hex!("2b8a3403cd097f95f3ba656e14b76c732b6b26d7f183330b11e36ef2bc028765"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::X86_64,
-        version: "29.3",
+        version: ProtocVersion::new(29, 3),
         hash: hex!("9a788036d8f9854f7b03c305df4777cf0e54e5b081e25bf15252da87e0e90875"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::AArch64,
-        version: "30.0",
+        version: ProtocVersion::new(30, 0),
         hash: hex!("5ab347b71fb8a87139cec36aac4bd0ee3ac3f4f2af9fc68ebdf556e1c0a665c6"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::X86_64,
-        version: "30.0",
+        version: ProtocVersion::new(30, 0),
         hash: hex!("2fbbc1818463d7e6d93c19a8dea839e663ca5f8579a52ef78c7688188335fa6c"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::AArch64,
-        version: "30.0",
+        version: ProtocVersion::new(30, 0),
         hash: hex!("7eb5b51d37bac410ba70ef91c404f90b1fabcb823712ff656582d34acc87ca74"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::X86_64,
-        version: "30.0",
+        version: ProtocVersion::new(30, 0),
         hash: hex!("96bf3a5fbeefd57d7dc0c20a2c7bb3f226ad84b79e5b509386824322017b9417"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::AArch64,
-        version: "30.1",
+        version: ProtocVersion::new(30, 1),
         hash: hex!("e866d3dc4775e8032721915e83e3fb6e1ab4def7199a49b4f95c4d1f6cf4c03a"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::X86_64,
-        version: "30.1",
+        version: ProtocVersion::new(30, 1),
         hash: hex!("5537e15ab0c0e610f809573948d3ec7d6ef387a07991e1c361a2a0e8cad983e5"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::AArch64,
-        version: "30.1",
+        version: ProtocVersion::new(30, 1),
         hash: hex!("03467cfd967de12a61406b7473e80204d3ae38f30f82855318186d696237e3b9"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::X86_64,
-        version: "30.1",
+        version: ProtocVersion::new(30, 1),
         hash: hex!("a4aeefd2f59ccce59cfa01a89fe58adb40bb9010f43adfca3c4fee7fd37ec2c5"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::AArch64,
-        version: "30.2",
+        version: ProtocVersion::new(30, 2),
         hash: hex!("a3173ea338ef91b1605b88c4f8120d6c8ccf36f744d9081991d595d0d4352996"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::X86_64,
-        version: "30.2",
+        version: ProtocVersion::new(30, 2),
         hash: hex!("327e9397c6fb3ea2a542513a3221334c6f76f7aa524a7d2561142b67b312a01f"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::AArch64,
-        version: "30.2",
+        version: ProtocVersion::new(30, 2),
         hash: hex!("92728c650f6cf2b6c37891ae04ef5bc2d4b5f32c5fbbd101eda623f90bb95f63"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::X86_64,
-        version: "30.2",
+        version: ProtocVersion::new(30, 2),
         hash: hex!("65675c3bb874a2d5f0c941e61bce6175090be25fe466f0ec2d4a6f5978333624"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::AArch64,
-        version: "31.0",
+        version: ProtocVersion::new(31, 0),
         hash: hex!("999f4c023366b0b68c5c65272ead7877e47a2670245a79904b83450575da7e19"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::X86_64,
-        version: "31.0",
+        version: ProtocVersion::new(31, 0),
         hash: hex!("24e2ed32060b7c990d5eb00d642fde04869d7f77c6d443f609353f097799dd42"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::AArch64,
-        version: "31.0",
+        version: ProtocVersion::new(31, 0),
         hash: hex!("1fbe70a8d646875f91b6fd57294f763145292b2c9e1374ab09d6e2124afdd950"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::X86_64,
-        version: "31.0",
+        version: ProtocVersion::new(31, 0),
         hash: hex!("0360d9b6d9e3d66958cf6274d8514da49e76d475fd0d712181dcc7e9e056f2c8"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::AArch64,
-        version: "31.1",
+        version: ProtocVersion::new(31, 1),
         hash: hex!("6c554de11cea04c56ebf8e45b54434019b1cd85223d4bbd25c282425e306ecc2"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::X86_64,
-        version: "31.1",
+        version: ProtocVersion::new(31, 1),
         hash: hex!("96553041f1a91ea0efee963cb16f462f5985b4d65365f3907414c360044d8065"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::AArch64,
-        version: "31.1",
+        version: ProtocVersion::new(31, 1),
         hash: hex!("4aeea0a34b0992847b03a8489a8dbedf3746de01109b74cc2ce9b6888a901ed9"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::X86_64,
-        version: "31.1",
+        version: ProtocVersion::new(31, 1),
         hash: hex!("485e87088b18614c25a99b1c0627918b3ff5b9fde54922fb1c920159fab7ba29"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::AArch64,
-        version: "33.0",
+        version: ProtocVersion::new(33, 0),
         hash: hex!("4b96bc91f8b54d829b8c3ca2207ff1ceb774843321e4fa5a68502faece584272"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::X86_64,
-        version: "33.0",
+        version: ProtocVersion::new(33, 0),
         hash: hex!("d99c011b799e9e412064244f0be417e5d76c9b6ace13a2ac735330fa7d57ad8f"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::AArch64,
-        version: "33.0",
+        version: ProtocVersion::new(33, 0),
         hash: hex!("3cf55dd47118bd2efda9cd26b74f8bbbfcf5beb1bf606bc56ad4c001b543f6d3"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::X86_64,
-        version: "33.0",
+        version: ProtocVersion::new(33, 0),
         hash: hex!("e4e50a703147a92d1a5a2d3a34c9e41717f67ade67d4be72b9a466eb8f22fe87"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::AArch64,
-        version: "33.2",
+        version: ProtocVersion::new(33, 2),
         hash: hex!("706662a332683aa2fffe1c4ea61588279d31679cd42d91c7d60a69651768edb8"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::X86_64,
-        version: "33.2",
+        version: ProtocVersion::new(33, 2),
         hash: hex!("b24b53f87c151bfd48b112fe4c3a6e6574e5198874f38036aff41df3456b8caf"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::AArch64,
-        version: "33.2",
+        version: ProtocVersion::new(33, 2),
         hash: hex!("5be1427127788c9f7dd7d606c3e69843dd3587327dea993917ffcb77e7234b44"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::X86_64,
-        version: "33.2",
+        version: ProtocVersion::new(33, 2),
         hash: hex!("dba51cfcc85076d56e7de01a647865c5a7f995c3dce427d5215b53e50b7be43f"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::AArch64,
-        version: "33.5",
+        version: ProtocVersion::new(33, 5),
         hash: hex!("2b0fcf9b2c32cbadccc0eb7a88b841fffecd4a06fc80acdba2b5be45e815c38a"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::X86_64,
-        version: "33.5",
+        version: ProtocVersion::new(33, 5),
         hash: hex!("24e58fb231d50306ee28491f33a170301e99540f7e29ca461e0e80fd1239f8d1"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::AArch64,
-        version: "33.5",
+        version: ProtocVersion::new(33, 5),
         hash: hex!("7084c6482e3bb416a33fe2162ba566711773b842e6953bf6cb181647b9ef57c0"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::X86_64,
-        version: "33.5",
+        version: ProtocVersion::new(33, 5),
         hash: hex!("7f31625f8bec4929082ae9209e101c1c03692624457cc6332f83736db495ee92"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::AArch64,
-        version: "34.0",
+        version: ProtocVersion::new(34, 0),
         hash: hex!("f0b8aad28be5ea6150c082f96ac57e028154afb9ee29f4ce092b5a39df8ae6c8"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::X86_64,
-        version: "34.0",
+        version: ProtocVersion::new(34, 0),
         hash: hex!("e9a91b6fcfe4177ec2cd35fc8f15c1e811fa0ecdef9372755cd6d3513d5faaab"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::AArch64,
-        version: "34.0",
+        version: ProtocVersion::new(34, 0),
         hash: hex!("3ef35187a3c8aed81ee57e792227e483e558fa56c93fce525e569bff55794c1a"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::X86_64,
-        version: "34.0",
+        version: ProtocVersion::new(34, 0),
         hash: hex!("d58fcd413a9ed458283d54023e409fd5cf767da4ed225d1ffaffd83cf2764f53"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::AArch64,
-        version: "34.1",
+        version: ProtocVersion::new(34, 1),
         hash: hex!("31c5e9e3c7bf013cf41fb97765ee255c140024a6b175b6cc9b64beddd7c23ba7"),
     },
     KnownVersion {
         os: OS::Linux,
         cpu: CPUArch::X86_64,
-        version: "34.1",
+        version: ProtocVersion::new(34, 1),
         hash: hex!("af27ea66cd26938fe48587804ca7d4817457a08350021a1c6e23a27ccc8c6904"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::AArch64,
-        version: "34.1",
+        version: ProtocVersion::new(34, 1),
         hash: hex!("2c7e92b8b578916937df132b3032e2e8e6c170862ecf7a8333094a6f3d03650c"),
     },
     KnownVersion {
         os: OS::OSX,
         cpu: CPUArch::X86_64,
-        version: "34.1",
+        version: ProtocVersion::new(34, 1),
         hash: hex!("ab124429c1f49951f03b6c0c0e911fec04e2c7c20de5c935e0cde7353bbd016c"),
     },
 ];
@@ -564,7 +1214,502 @@ mod tests {
     #[test]
     fn test_known_hash() {
         // ensure we know a hash for the current platform
-        known_hash(OS::current(), CPUArch::current(), LATEST_VERSION).unwrap();
+        let current = Platform::detect().unwrap();
+        let release = select_release(
+            KNOWN_VERSIONS,
+            current,
+            LATEST_VERSION,
+            UniversalBinary::Never,
+        )
+        .unwrap();
+        assert_eq!(current, release.platform);
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(OS::current(), OS::detect().unwrap());
+        assert_eq!(CPUArch::current(), CPUArch::detect().unwrap());
+
+        let err = "freebsd".parse::<OS>().unwrap_err();
+        assert_eq!("unsupported OS: freebsd", err.to_string());
+        let err = "riscv64".parse::<CPUArch>().unwrap_err();
+        assert_eq!("unsupported arch: riscv64", err.to_string());
+    }
+
+    #[test]
+    fn test_parse() {
+        for os in OS::all() {
+            assert_eq!(*os, os.to_string().parse().unwrap());
+            assert_eq!(*os, os.rust_identifier().parse().unwrap());
+        }
+        assert_eq!(OS::OSX, "macos".parse().unwrap());
+        assert_eq!(OS::Linux, "linux".parse().unwrap());
+
+        for cpu in CPUArch::all_with_releases() {
+            assert_eq!(*cpu, cpu.to_string().parse().unwrap());
+            assert_eq!(*cpu, cpu.code_label().parse().unwrap());
+        }
+        // every OS and CPU pair from all() has releases
+        for os in OS::all() {
+            for cpu in CPUArch::all() {
+                assert!(os.has_release(*cpu), "{os}-{cpu}");
+            }
+        }
+        assert_eq!(CPUArch::AArch64, "aarch64".parse().unwrap());
+        assert_eq!(CPUArch::X86_64, "x86_64".parse().unwrap());
+
+        for platform in Platform::all() {
+            assert_eq!(platform, platform.to_string().parse().unwrap());
+        }
+        assert_eq!(
+            Platform::new(OS::OSX, CPUArch::UniversalBinary),
+            "osx-universal_binary".parse().unwrap()
+        );
+        assert_eq!(
+            Platform::new(OS::Linux, CPUArch::AArch64),
+            "aarch64-unknown-linux-gnu".parse().unwrap()
+        );
+        assert!("linux".parse::<Platform>().is_err());
+        let err = "windows-x86_64".parse::<Platform>().unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("known platforms: linux-aarch_64, linux-x86_64, linux-x86_32, osx-aarch_64, osx-x86_64, osx-universal_binary"),
+            "unexpected error: {err}"
+        );
+    }
+
+    #[test]
+    fn test_from_target_triple() {
+        let tests = [
+            ("aarch64-apple-darwin", Some((OS::OSX, CPUArch::AArch64))),
+            ("x86_64-apple-darwin", Some((OS::OSX, CPUArch::X86_64))),
+            (
+                "x86_64-unknown-linux-gnu",
+                Some((OS::Linux, CPUArch::X86_64)),
+            ),
+            (
+                "x86_64-unknown-linux-musl",
+                Some((OS::Linux, CPUArch::X86_64)),
+            ),
+            (
+                "aarch64-unknown-linux-gnu",
+                Some((OS::Linux, CPUArch::AArch64)),
+            ),
+            ("i686-unknown-linux-gnu", Some((OS::Linux, CPUArch::X86_32))),
+            ("aarch64-linux-android", None),
+            ("x86_64-pc-windows-msvc", None),
+            ("riscv64gc-unknown-linux-gnu", None),
+            ("x86_64-unknown-freebsd", None),
+            ("", None),
+        ];
+        for (triple, expected) in tests {
+            let platform = Platform::from_target_triple(triple).ok();
+            let expected = expected.map(|(os, cpu)| Platform::new(os, cpu));
+            assert_eq!(expected, platform, "triple={triple}");
+        }
+    }
+
+    #[test]
+    fn test_select_release() {
+        const TEST_VERSIONS: &[KnownVersion] = &[
+            KnownVersion {
+                os: OS::OSX,
+                cpu: CPUArch::AArch64,
+                version: ProtocVersion::new(1, 0),
+                hash: [1; 32],
+            },
+            KnownVersion {
+                os: OS::OSX,
+                cpu: CPUArch::UniversalBinary,
+                version: ProtocVersion::new(1, 0),
+                hash: [2; 32],
+            },
+            KnownVersion {
+                os: OS::Linux,
+                cpu: CPUArch::X86_64,
+                version: ProtocVersion::new(1, 0),
+                hash: [3; 32],
+            },
+            KnownVersion {
+                os: OS::Linux,
+                cpu: CPUArch::X86_32,
+                version: ProtocVersion::new(1, 0),
+                hash: [4; 32],
+            },
+            KnownVersion {
+                os: OS::Linux,
+                cpu: CPUArch::X86_32,
+                version: ProtocVersion::new(0, 9),
+                hash: [5; 32],
+            },
+        ];
+
+        let select = |os, cpu, universal_binary| {
+            select_release(
+                TEST_VERSIONS,
+                Platform::new(os, cpu),
+                ProtocVersion::new(1, 0),
+                universal_binary,
+            )
+            .map(|release| (release.platform.cpu, release.hash[0]))
+            .ok()
+        };
+
+        // the CPU-specific release exists
+        assert_eq!(
+            select(OS::OSX, CPUArch::AArch64, UniversalBinary::Fallback),
+            Some((CPUArch::AArch64, 1))
+        );
+        assert_eq!(
+            select(OS::OSX, CPUArch::AArch64, UniversalBinary::Never),
+            Some((CPUArch::AArch64, 1))
+        );
+        assert_eq!(
+            select(OS::OSX, CPUArch::AArch64, UniversalBinary::Prefer),
+            Some((CPUArch::UniversalBinary, 2))
+        );
+
+        // the CPU-specific release is missing
+        assert_eq!(
+            select(OS::OSX, CPUArch::X86_64, UniversalBinary::Fallback),
+            Some((CPUArch::UniversalBinary, 2))
+        );
+        assert_eq!(
+            select(OS::OSX, CPUArch::X86_64, UniversalBinary::Never),
+            None
+        );
+
+        // Linux never uses the universal binary
+        assert_eq!(
+            select(OS::Linux, CPUArch::X86_64, UniversalBinary::Prefer),
+            Some((CPUArch::X86_64, 3))
+        );
+        assert_eq!(
+            select(OS::Linux, CPUArch::AArch64, UniversalBinary::Prefer),
+            None
+        );
+
+        // 32-bit releases are only used on x86_32
+        assert_eq!(
+            select(OS::Linux, CPUArch::X86_32, UniversalBinary::Fallback),
+            Some((CPUArch::X86_32, 4))
+        );
+        assert!(
+            select_release(
+                TEST_VERSIONS,
+                Platform::new(OS::Linux, CPUArch::X86_64),
+                ProtocVersion::new(0, 9),
+                UniversalBinary::Fallback,
+            )
+            .is_err()
+        );
+    }
+
+    const RESOLVE_TEST_VERSIONS: &[KnownVersion] = &[
+        KnownVersion {
+            os: OS::Linux,
+            cpu: CPUArch::X86_64,
+            version: ProtocVersion::new(30, 0),
+            hash: [1; 32],
+        },
+        KnownVersion {
+            os: OS::Linux,
+            cpu: CPUArch::AArch64,
+            version: ProtocVersion::new(30, 0),
+            hash: [2; 32],
+        },
+        KnownVersion {
+            os: OS::Linux,
+            cpu: CPUArch::X86_64,
+            version: ProtocVersion::new(31, 9),
+            hash: [3; 32],
+        },
+        KnownVersion {
+            os: OS::Linux,
+            cpu: CPUArch::AArch64,
+            version: ProtocVersion::new(31, 9),
+            hash: [4; 32],
+        },
+        KnownVersion {
+            os: OS::Linux,
+            cpu: CPUArch::X86_64,
+            version: ProtocVersion::new(31, 10),
+            hash: [5; 32],
+        },
+        KnownVersion {
+            os: OS::Linux,
+            cpu: CPUArch::X86_64,
+            version: ProtocVersion {
+                prerelease: Some(1),
+                ..ProtocVersion::new(32, 0)
+            },
+            hash: [6; 32],
+        },
+    ];
+
+    #[test]
+    fn test_resolve_release() {
+        let resolve = |cpu, requirement: &str| {
+            resolve_release(
+                RESOLVE_TEST_VERSIONS,
+                Platform::new(OS::Linux, cpu),
+                &requirement.parse().unwrap(),
+                UniversalBinary::Fallback,
+                false,
+                &[],
+            )
+            .map(|release| (release.version.to_string(), release.hash[0]))
+            .map_err(|e| e.to_string())
+        };
+
+        // the newest version is compared numerically
+        assert_eq!(
+            Ok((String::from("31.10"), 5)),
+            resolve(CPUArch::X86_64, ">=30")
+        );
+        // the newest version for this platform
+        assert_eq!(
+            Ok((String::from("31.9"), 4)),
+            resolve(CPUArch::AArch64, ">=30")
+        );
+        assert_eq!(
+            Ok((String::from("30.0"), 1)),
+            resolve(CPUArch::X86_64, "~30")
+        );
+        assert_eq!(
+            Ok((String::from("31.9"), 3)),
+            resolve(CPUArch::X86_64, "31.9")
+        );
+        assert_eq!(
+            Err(String::from(
+                "no known protoc version matches >=32 for linux-x86_64: \
+                the latest known version for linux-x86_64 is 31.10"
+            )),
+            resolve(CPUArch::X86_64, ">=32")
+        );
+        assert_eq!(
+            Err(String::from(
+                "unknown hash for linux aarch_64 31.10: protoc 31.10 is only known for \
+                linux-x86_64; the latest known version for linux-aarch_64 is 31.9"
+            )),
+            resolve(CPUArch::AArch64, "31.10")
+        );
+
+        assert_eq!(
+            ProtocVersion::new(31, 10),
+            latest_release_version(RESOLVE_TEST_VERSIONS)
+        );
+    }
+
+    #[test]
+    fn test_unknown_release_hint() {
+        let linux = Platform::new(OS::Linux, CPUArch::X86_64);
+        let hint = |platform: Platform, version: ProtocVersion| {
+            let candidates = candidate_releases(platform, UniversalBinary::Fallback);
+            unknown_release_hint(RESOLVE_TEST_VERSIONS, platform, &candidates, Some(version))
+        };
+        assert_eq!(
+            "the latest known version for linux-x86_64 is 31.10; did you mean 30.0 or 31.9?",
+            hint(linux, ProtocVersion::new(31, 0))
+        );
+        assert_eq!(
+            "the latest known version for linux-x86_64 is 31.10; did you mean 30.0?",
+            hint(linux, ProtocVersion::new(29, 0))
+        );
+        assert_eq!(
+            "the latest known version for linux-x86_64 is 31.10",
+            hint(linux, ProtocVersion::new(99, 0))
+        );
+        assert_eq!(
+            "no protoc versions are known for osx-x86_64",
+            hint(
+                Platform::new(OS::OSX, CPUArch::X86_64),
+                ProtocVersion::new(30, 0)
+            )
+        );
+    }
+
+    #[test]
+    fn test_resolve_release_prereleases() {
+        let resolve = |requirement: &str, prereleases| {
+            resolve_release(
+                RESOLVE_TEST_VERSIONS,
+                Platform::new(OS::Linux, CPUArch::X86_64),
+                &requirement.parse().unwrap(),
+                UniversalBinary::Fallback,
+                prereleases,
+                &[],
+            )
+            .unwrap()
+            .version
+            .to_string()
+        };
+
+        // prereleases are only used when requested
+        assert_eq!("31.10", resolve("*", false));
+        assert_eq!("32.0-rc1", resolve("*", true));
+        assert_eq!("32.0-rc1", resolve("32.0-rc1", false));
+        assert_eq!("32.0-rc1", resolve(">=32.0-rc1", false));
+    }
+
+    #[test]
+    fn test_compatible_releases_constant() {
+        for (runs_on, release) in COMPATIBLE_RELEASES {
+            assert!(runs_on.os.has_release(runs_on.cpu), "{runs_on}");
+            assert!(release.os.has_release(release.cpu), "{release}");
+            assert_ne!(runs_on, release);
+        }
+    }
+
+    #[test]
+    fn test_known_releases() {
+        let linux = Platform::new(OS::Linux, CPUArch::X86_64);
+        assert_eq!(Some(LATEST_VERSION), latest_for(linux));
+        assert_eq!(Some(ProtocVersion::new(27, 0)), versions_for(linux).next());
+        assert!(
+            versions_for(Platform::new(OS::OSX, CPUArch::AArch64))
+                .all(|version| version > ProtocVersion::new(27, 0))
+        );
+        assert_eq!(
+            None,
+            latest_for(Platform::new(OS::Linux, CPUArch::UniversalBinary))
+        );
+
+        let release = known_releases()
+            .find(|known| known.platform() == linux && known.version() == LATEST_VERSION)
+            .unwrap();
+        assert_eq!(
+            release.hash(),
+            select_release(
+                KNOWN_VERSIONS,
+                linux,
+                LATEST_VERSION,
+                UniversalBinary::Never
+            )
+            .unwrap()
+            .hash
+        );
+    }
+
+    #[test]
+    fn test_resolve_release_excluded() {
+        const LINUX: Platform = Platform::new(OS::Linux, CPUArch::X86_64);
+        const TEST_VERSIONS: &[KnownVersion] = &[
+            KnownVersion::new(ProtocVersion::new(31, 9), LINUX, [1; 32]),
+            KnownVersion::new(ProtocVersion::new(31, 10), LINUX, [2; 32]),
+        ];
+        let resolve = |requirement: &str| {
+            resolve_release(
+                TEST_VERSIONS,
+                LINUX,
+                &requirement.parse().unwrap(),
+                UniversalBinary::Fallback,
+                false,
+                &[ProtocVersion::new(31, 10)],
+            )
+            .unwrap()
+            .version
+            .to_string()
+        };
+
+        // excluded versions are skipped, unless the requirement is exact
+        assert_eq!("31.9", resolve(">=30"));
+        assert_eq!("31.10", resolve("31.10"));
+    }
+
+    #[test]
+    fn test_minor_releases_behind() {
+        let known_versions = ["33.4", "33.5", "34.0", "34.1", "34.1", "35.0-rc1"]
+            .map(|version| version.parse::<ProtocVersion>().unwrap());
+        for (version, expected) in [
+            ("33.4", 3),
+            ("33.5", 2),
+            ("34.0", 1),
+            ("34.1", 0),
+            ("35.0-rc1", 0),
+            ("3.20.3", 4),
+        ] {
+            let version = version.parse().unwrap();
+            assert_eq!(
+                expected,
+                minor_releases_behind(version, known_versions),
+                "version={version}"
+            );
+        }
+        assert_eq!(
+            0,
+            minor_releases_behind(
+                LATEST_VERSION,
+                versions_for(Platform::new(OS::Linux, CPUArch::X86_64))
+            )
+        );
+    }
+
+    #[test]
+    fn test_with_trusted_hashes() {
+        let linux = Platform::new(OS::Linux, CPUArch::X86_64);
+        let trusted = [
+            KnownVersion::new(ProtocVersion::new(99, 0), linux, [1; 32]),
+            KnownVersion::new(LATEST_VERSION, linux, [2; 32]),
+        ];
+        let known_versions = with_trusted_hashes(&trusted);
+        assert_eq!(KNOWN_VERSIONS.len() + 1, known_versions.len());
+        let select = |version| {
+            select_release(&known_versions, linux, version, UniversalBinary::Never)
+                .unwrap()
+                .hash
+        };
+        assert_eq!([1; 32], select(ProtocVersion::new(99, 0)));
+        assert_eq!([2; 32], select(LATEST_VERSION));
+
+        // requirements match trusted versions
+        assert_eq!(
+            ProtocVersion::new(99, 0),
+            resolve_release(
+                &known_versions,
+                linux,
+                &VersionReq::any(),
+                UniversalBinary::Never,
+                false,
+                &[]
+            )
+            .unwrap()
+            .version
+        );
+    }
+
+    #[test]
+    fn test_parse_hash() {
+        assert_eq!([0xab; 32], parse_hash(&"ab".repeat(32)).unwrap());
+        assert_eq!([0xab; 32], parse_hash(&"AB".repeat(32)).unwrap());
+        let hash = parse_hash(&"0f".repeat(32)).unwrap();
+        assert_eq!("0f".repeat(32), format_hash(&hash));
+        for invalid in ["abc", &"zz".repeat(32), &"é".repeat(32)] {
+            let err = parse_hash(invalid).unwrap_err();
+            assert!(
+                err.to_string().starts_with("invalid sha256: "),
+                "unexpected error: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_release_info_constant() {
+        for pair in RELEASE_INFO.windows(2) {
+            assert!(pair[0].version < pair[1].version);
+        }
+        for info in RELEASE_INFO {
+            assert!(
+                KNOWN_VERSIONS
+                    .iter()
+                    .any(|known| known.version == info.version),
+                "no known release for {}",
+                info.version
+            );
+            assert_eq!(Some(info), release_info(info.version));
+        }
+        assert_eq!(None, release_info(ProtocVersion::new(99, 0)));
+        assert_eq!("2024-05-23", ReleaseDate::new(2024, 5, 23).to_string());
     }
 
     #[test]
@@ -573,27 +1718,32 @@ mod tests {
         struct KnownVersionKey {
             os: OS,
             cpu: CPUArch,
-            version: String,
+            version: ProtocVersion,
         }
         // check that KNOWN_VERSIONS is increasing and unique
         let mut all_versions = HashSet::new();
         let mut last_version = KNOWN_VERSIONS[0].version;
+        let mut last_release_version = last_version;
         for known_version in KNOWN_VERSIONS {
-            // This should be a semver comparsion, but is testing a string comparion.
-            // This will work with protoc versions since they are two digits, but can easily fail
-            // e.g. if there are a lot of point releases, because "27.10" should be greater than "27.9"
             assert!(known_version.version >= last_version);
             last_version = known_version.version;
+            if !last_version.is_prerelease() {
+                last_release_version = last_version;
+            }
 
             let key = KnownVersionKey {
                 os: known_version.os,
                 cpu: known_version.cpu,
-                version: known_version.version.to_string(),
+                version: known_version.version,
             };
+            assert!(
+                known_version.os.has_release(known_version.cpu),
+                "no release exists for {key:?}"
+            );
             let newly_inserted = all_versions.insert(key.clone());
             assert!(newly_inserted, "duplicate version: {key:?}");
         }
 
-        assert_eq!(LATEST_VERSION, last_version);
+        assert_eq!(LATEST_VERSION, last_release_version);
     }
 }
@@ -4,4 +4,37 @@ fn test_types_are_public() {
     const TEST_MESSAGE: &str = "test message";
     let err = dlprotoc::Error::from_string(TEST_MESSAGE.to_string());
     assert_eq!(TEST_MESSAGE, err.to_string());
+    assert_eq!(dlprotoc::ErrorKind::Other, err.kind());
+}
+
+#[test]
+fn test_known_releases_are_public() {
+    let latest = dlprotoc::known_releases()
+        .map(dlprotoc::KnownVersion::version)
+        .max();
+    assert_eq!(Some(dlprotoc::LATEST_VERSION), latest);
+
+    let platform = dlprotoc::known_releases().next().unwrap().platform();
+    assert!(dlprotoc::versions_for(platform).count() > 0);
+    assert!(dlprotoc::latest_for(platform).is_some());
+}
+
+#[test]
+fn test_release_info_is_public() {
+    let info = dlprotoc::release_info(dlprotoc::LATEST_VERSION);
+    if let Some(info) = info {
+        assert_eq!(dlprotoc::LATEST_VERSION, info.version());
+        assert!(!info.is_known_bad());
+    }
+    let date = dlprotoc::ReleaseDate::new(2024, 5, 23);
+    assert_eq!("2024-05-23", date.to_string());
+}
+
+#[test]
+fn test_strategy_is_public() {
+    let strategy = "system-then-download"
+        .parse::<dlprotoc::Strategy>()
+        .unwrap();
+    assert_eq!(dlprotoc::Strategy::SystemThenDownload, strategy);
+    let _config = dlprotoc::Config::new().strategy(strategy);
 }